@@ -0,0 +1,506 @@
+use crate::registers::{CpuFlag, Registers};
+
+/* Arithmetic and logic operations. They return their result and update the flags register. */
+impl Registers {
+    pub fn alu16_add(&mut self, a: u16, b: u16) -> u16 {
+        /* Z is left untouched, H and C are the carries out of bits 11 and 15. */
+        let (res, overflow) = a.overflowing_add(b);
+
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF);
+        self.set_flag(CpuFlag::C, overflow);
+
+        res
+    }
+
+    pub fn alu8_add(&mut self, a: u8, b: u8) -> u8 {
+        let (res, overflow) = a.overflowing_add(b);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, (a & 0x0F) + (b & 0x0F) > 0x0F);
+        self.set_flag(CpuFlag::C, overflow);
+
+        res
+    }
+
+    pub fn alu8_adc(&mut self, a: u8, b: u8) -> u8 {
+        let carry = match self.get_flag(CpuFlag::C) {
+            true => 1,
+            false => 0,
+        };
+
+        let wide = a as u16 + b as u16 + carry;
+        let (res, overflow) = (wide as u8, wide > 0xFF);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, (a & 0x0F) + (b & 0x0F) + carry as u8 > 0x0F);
+        self.set_flag(CpuFlag::C, overflow);
+
+        res
+    }
+
+    pub fn alu8_sub(&mut self, a: u8, b: u8) -> u8 {
+        let (res, overflow) = a.overflowing_sub(b);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, true);
+        self.set_flag(CpuFlag::H, (a & 0x0F) < (b & 0x0F));
+        self.set_flag(CpuFlag::C, overflow);
+
+        res
+    }
+
+    pub fn alu8_sub_with_borrow(&mut self, a: u8, b: u8) -> u8 {
+        let carry = match self.get_flag(CpuFlag::C) {
+            true => 1,
+            false => 0,
+        };
+
+        let wide = (a as u16).wrapping_sub(b as u16 + carry);
+        let (res, overflow) = (wide as u8, wide > 0xFF);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, true);
+        self.set_flag(CpuFlag::H, (a & 0x0F) < (b & 0x0F) + carry as u8);
+        self.set_flag(CpuFlag::C, overflow);
+
+        res
+    }
+
+    pub fn alu8_and(&mut self, a: u8, b: u8) -> u8 {
+        let res = a & b;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, true);
+        self.set_flag(CpuFlag::C, false);
+
+        res
+    }
+
+    pub fn alu8_or(&mut self, a: u8, b: u8) -> u8 {
+        let res = a | b;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, false);
+
+        res
+    }
+
+    pub fn alu8_xor(&mut self, a: u8, b: u8) -> u8 {
+        let res = a ^ b;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, false);
+
+        res
+    }
+
+    pub fn alu8_cp(&mut self, a: u8, b: u8) {
+        /* Same as SUB, but the result is thrown away. */
+        self.alu8_sub(a, b);
+    }
+
+    pub fn alu8_inc(&mut self, n: u8) -> u8 {
+        let res = n.wrapping_add(1);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, (n & 0x0F) == 0x0F);
+
+        res
+    }
+
+    pub fn alu8_dec(&mut self, n: u8) -> u8 {
+        let res = n.wrapping_sub(1);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, true);
+        self.set_flag(CpuFlag::H, (n & 0x0F) == 0);
+
+        res
+    }
+
+    pub fn alu8_rl(&mut self, n: u8) -> u8 {
+        let old_carry = if self.get_flag(CpuFlag::C) { 1 } else { 0 };
+        let new_carry = n & 0x80 == 0x80;
+        let res = ((n << 1) & !0b1) | old_carry;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_rlc(&mut self, n: u8) -> u8 {
+        let new_carry = n & 0x80 == 0x80;
+        let res = n.rotate_left(1);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_rrc(&mut self, n: u8) -> u8 {
+        let new_carry = n & 0x01 == 0x01;
+        let res = n.rotate_right(1);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_rr(&mut self, n: u8) -> u8 {
+        let old_carry = if self.get_flag(CpuFlag::C) { 0x80 } else { 0 };
+        let new_carry = n & 0x01 == 0x01;
+        let res = (n >> 1) | old_carry;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_sla(&mut self, n: u8) -> u8 {
+        let new_carry = n & 0x80 == 0x80;
+        let res = n << 1;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_sra(&mut self, n: u8) -> u8 {
+        /* Bit 7 is kept as is. */
+        let new_carry = n & 0x01 == 0x01;
+        let res = (n >> 1) | (n & 0x80);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_srl(&mut self, n: u8) -> u8 {
+        let new_carry = n & 0x01 == 0x01;
+        let res = n >> 1;
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, new_carry);
+
+        res
+    }
+
+    pub fn alu8_swap(&mut self, n: u8) -> u8 {
+        let res = n.rotate_left(4);
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, false);
+
+        res
+    }
+
+    pub fn alu8_daa(&mut self, a: u8) -> u8 {
+        /* Adjust A back into packed BCD after an addition or subtraction, using the flags left by
+         * that operation.
+         */
+        let mut adjust = 0;
+        let mut carry = self.get_flag(CpuFlag::C);
+
+        if self.get_flag(CpuFlag::N) {
+            if self.get_flag(CpuFlag::H) {
+                adjust |= 0x06;
+            }
+            if carry {
+                adjust |= 0x60;
+            }
+        } else {
+            if self.get_flag(CpuFlag::H) || (a & 0x0F) > 0x09 {
+                adjust |= 0x06;
+            }
+            if carry || a > 0x99 {
+                adjust |= 0x60;
+                carry = true;
+            }
+        }
+
+        let res = if self.get_flag(CpuFlag::N) {
+            a.wrapping_sub(adjust)
+        } else {
+            a.wrapping_add(adjust)
+        };
+
+        self.set_flag(CpuFlag::Z, res == 0);
+        self.set_flag(CpuFlag::H, false);
+        self.set_flag(CpuFlag::C, carry);
+
+        res
+    }
+
+    pub fn alu16_add_sp(&mut self, sp: u16, r8: i8) -> u16 {
+        /* Used by ADD SP,r8 and LD HL,SP+r8: H and C come from the unsigned addition of the low
+         * byte of SP and the immediate.
+         */
+        let imm = r8 as u8 as u16;
+
+        self.set_flag(CpuFlag::Z, false);
+        self.set_flag(CpuFlag::N, false);
+        self.set_flag(CpuFlag::H, (sp & 0x0F) + (imm & 0x0F) > 0x0F);
+        self.set_flag(CpuFlag::C, (sp & 0xFF) + imm > 0xFF);
+
+        sp.wrapping_add(r8 as u16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registers::{CpuFlag, Registers};
+
+    fn flags(regs: &Registers) -> (bool, bool, bool, bool) {
+        (
+            regs.get_flag(CpuFlag::Z),
+            regs.get_flag(CpuFlag::N),
+            regs.get_flag(CpuFlag::H),
+            regs.get_flag(CpuFlag::C),
+        )
+    }
+
+    fn with_carry(carry: bool) -> Registers {
+        let mut regs = Registers::new();
+        regs.set_flag(CpuFlag::C, carry);
+        regs
+    }
+
+    /* Reference model: H is bit 4 of a ^ b ^ res, C is computed on wider integers. */
+    fn reference_add(a: u8, b: u8, carry: bool) -> (u8, (bool, bool, bool, bool)) {
+        let wide = a as u32 + b as u32 + carry as u32;
+        let res = wide as u8;
+        (res, (res == 0, false, (a ^ b ^ res) & 0x10 > 0, wide > 0xFF))
+    }
+
+    fn reference_sub(a: u8, b: u8, carry: bool) -> (u8, (bool, bool, bool, bool)) {
+        let wide = a as i32 - b as i32 - carry as i32;
+        let res = wide as u8;
+        (res, (res == 0, true, (a ^ b ^ res) & 0x10 > 0, wide < 0))
+    }
+
+    #[test]
+    fn add_and_adc_all_operands() {
+        for a in 0..=0xFF {
+            for b in 0..=0xFF {
+                let mut regs = with_carry(true);
+                assert_eq!((regs.alu8_add(a, b), flags(&regs)), reference_add(a, b, false));
+
+                for &carry in [false, true].iter() {
+                    let mut regs = with_carry(carry);
+                    assert_eq!((regs.alu8_adc(a, b), flags(&regs)), reference_add(a, b, carry));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sub_sbc_and_cp_all_operands() {
+        for a in 0..=0xFF {
+            for b in 0..=0xFF {
+                let mut regs = with_carry(true);
+                assert_eq!((regs.alu8_sub(a, b), flags(&regs)), reference_sub(a, b, false));
+
+                let mut regs = with_carry(true);
+                regs.alu8_cp(a, b);
+                assert_eq!(flags(&regs), reference_sub(a, b, false).1);
+
+                for &carry in [false, true].iter() {
+                    let mut regs = with_carry(carry);
+                    let res = regs.alu8_sub_with_borrow(a, b);
+                    assert_eq!((res, flags(&regs)), reference_sub(a, b, carry));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn logic_all_operands() {
+        for a in 0..=0xFF {
+            for b in 0..=0xFF {
+                let mut regs = with_carry(true);
+                assert_eq!(regs.alu8_and(a, b), a & b);
+                assert_eq!(flags(&regs), (a & b == 0, false, true, false));
+
+                let mut regs = with_carry(true);
+                assert_eq!(regs.alu8_or(a, b), a | b);
+                assert_eq!(flags(&regs), (a | b == 0, false, false, false));
+
+                let mut regs = with_carry(true);
+                assert_eq!(regs.alu8_xor(a, b), a ^ b);
+                assert_eq!(flags(&regs), (a ^ b == 0, false, false, false));
+            }
+        }
+    }
+
+    #[test]
+    fn inc_dec_keep_carry() {
+        for n in 0..=0xFF {
+            for &carry in [false, true].iter() {
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_inc(n);
+                assert_eq!((res, flags(&regs)), (n.wrapping_add(1), (res == 0, false, n & 0x0F == 0x0F, carry)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_dec(n);
+                assert_eq!((res, flags(&regs)), (n.wrapping_sub(1), (res == 0, true, n & 0x0F == 0, carry)));
+            }
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        /* Examples from the GB CPU manual. */
+        let mut regs = with_carry(false);
+        assert_eq!(regs.alu8_add(0x3A, 0xC6), 0x00);
+        assert_eq!(flags(&regs), (true, false, true, true));
+
+        let mut regs = with_carry(true);
+        assert_eq!(regs.alu8_adc(0xE1, 0x0F), 0xF1);
+        assert_eq!(flags(&regs), (false, false, true, false));
+
+        let mut regs = with_carry(false);
+        assert_eq!(regs.alu8_sub(0x3E, 0x0F), 0x2F);
+        assert_eq!(flags(&regs), (false, true, true, false));
+
+        let mut regs = with_carry(true);
+        assert_eq!(regs.alu8_sub_with_borrow(0x3B, 0x4F), 0xEB);
+        assert_eq!(flags(&regs), (false, true, true, true));
+    }
+
+    #[test]
+    fn add16_leaves_zero_flag() {
+        for a in (0..=0xFFFFu32).step_by(0x0107) {
+            for b in (0..=0xFFFFu32).step_by(0x0089) {
+                for &zero in [false, true].iter() {
+                    let mut regs = Registers::new();
+                    regs.set_flag(CpuFlag::Z, zero);
+
+                    let res = regs.alu16_add(a as u16, b as u16);
+                    let half = (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF;
+
+                    assert_eq!(res, (a + b) as u16);
+                    assert_eq!(flags(&regs), (zero, false, half, a + b > 0xFFFF));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_sp_signed_all_offsets() {
+        for &sp in [0x0000u16, 0x000F, 0x00FF, 0x0FF8, 0xCFFF, 0xDFF0, 0xFFF8, 0xFFFF].iter() {
+            for r8 in -128i8..=127 {
+                let mut regs = Registers::new();
+                regs.set_flag(CpuFlag::Z, true);
+
+                let res = regs.alu16_add_sp(sp, r8);
+                let imm = r8 as u8;
+                let low = sp as u8;
+
+                assert_eq!(res, (sp as i32 + r8 as i32) as u16);
+                assert_eq!(
+                    flags(&regs),
+                    (false, false, (low ^ imm ^ (low.wrapping_add(imm))) & 0x10 > 0, low as u16 + imm as u16 > 0xFF)
+                );
+            }
+        }
+    }
+
+    fn to_bcd(n: u32) -> u8 {
+        (((n / 10) << 4) | (n % 10)) as u8
+    }
+
+    #[test]
+    fn daa_after_bcd_operations() {
+        for x in 0..100 {
+            for y in 0..100 {
+                for &carry in [false, true].iter() {
+                    let mut regs = with_carry(carry);
+                    let sum = regs.alu8_adc(to_bcd(x), to_bcd(y));
+                    let res = regs.alu8_daa(sum);
+                    let expected = x + y + carry as u32;
+
+                    assert_eq!(res, to_bcd(expected % 100));
+                    assert_eq!(flags(&regs), (expected == 100 || expected == 0, false, false, expected >= 100));
+
+                    let mut regs = with_carry(carry);
+                    let diff = regs.alu8_sub_with_borrow(to_bcd(x), to_bcd(y));
+                    let res = regs.alu8_daa(diff);
+                    let expected = (x + 200 - y - carry as u32) % 100;
+
+                    assert_eq!(res, to_bcd(expected));
+                    assert_eq!(flags(&regs), (expected == 0, true, false, x < y + carry as u32));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotates_and_shifts() {
+        for n in 0..=0xFFu8 {
+            for &carry in [false, true].iter() {
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_rl(n);
+                assert_eq!((res, flags(&regs)), ((n << 1) | carry as u8, (res == 0, false, false, n & 0x80 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_rr(n);
+                assert_eq!((res, flags(&regs)), ((n >> 1) | (carry as u8) << 7, (res == 0, false, false, n & 1 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_rlc(n);
+                assert_eq!((res, flags(&regs)), (n.rotate_left(1), (res == 0, false, false, n & 0x80 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_rrc(n);
+                assert_eq!((res, flags(&regs)), (n.rotate_right(1), (res == 0, false, false, n & 1 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_sla(n);
+                assert_eq!((res, flags(&regs)), (n << 1, (res == 0, false, false, n & 0x80 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_sra(n);
+                assert_eq!((res, flags(&regs)), (((n as i8) >> 1) as u8, (res == 0, false, false, n & 1 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_srl(n);
+                assert_eq!((res, flags(&regs)), (n >> 1, (res == 0, false, false, n & 1 > 0)));
+
+                let mut regs = with_carry(carry);
+                let res = regs.alu8_swap(n);
+                assert_eq!((res, flags(&regs)), (((n & 0x0F) << 4) | ((n & 0xF0) >> 4), (res == 0, false, false, false)));
+            }
+        }
+    }
+}
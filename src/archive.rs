@@ -0,0 +1,179 @@
+/* ROM files can be stored compressed, as a .zip or a .gz. Everything about archives stays in here,
+ * the emulator itself only ever gets the plain ROM bytes.
+ */
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "archive")]
+use crate::header::MAX_ROM_SIZE;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/* Extensions looked for when picking the ROM out of a zip archive. */
+#[cfg(feature = "archive")]
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+/* Reads a ROM, decompressing it if it's an archive. For zip archives entry selects the file to
+ * extract, otherwise the first .gb or .gbc one is used.
+ */
+pub fn read_rom_file(path: &Path, entry: Option<&str>) -> io::Result<Vec<u8>> {
+    extract_rom(fs::read(path)?, entry)
+}
+
+/* Archives are recognized by their magic bytes rather than their extension, anything else is
+ * returned untouched.
+ */
+pub fn extract_rom(data: Vec<u8>, entry: Option<&str>) -> io::Result<Vec<u8>> {
+    if data.starts_with(ZIP_MAGIC) {
+        extract_zip(data, entry)
+    } else if data.starts_with(GZIP_MAGIC) {
+        extract_gzip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(feature = "archive")]
+fn extract_zip(data: Vec<u8>, entry: Option<&str>) -> io::Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(data))?;
+
+    let name = match entry {
+        Some(name) => name.to_string(),
+        None => first_rom_name(&mut archive)?,
+    };
+
+    let file = archive.by_name(&name)?;
+    read_decompressed(file)
+}
+
+#[cfg(feature = "archive")]
+fn extract_gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    read_decompressed(flate2::read::GzDecoder::new(data))
+}
+
+/* The sizes archives declare can't be trusted, so stop reading past the biggest possible ROM
+ * instead of decompressing whatever a bomb expands to.
+ */
+#[cfg(feature = "archive")]
+fn read_decompressed<R: io::Read>(reader: R) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "compressed file bigger than any ROM"));
+    }
+
+    Ok(rom)
+}
+
+#[cfg(not(feature = "archive"))]
+fn extract_zip(_data: Vec<u8>, _entry: Option<&str>) -> io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "archive"))]
+fn extract_gzip(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "archive"))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "compressed ROM, but built without the archive feature")
+}
+
+#[cfg(feature = "archive")]
+fn is_rom_name(name: &str) -> bool {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ROM_EXTENSIONS.iter().any(|rom_ext| ext.eq_ignore_ascii_case(rom_ext)),
+        None => false,
+    }
+}
+
+/* First ROM in archive order, which file_names() doesn't keep. */
+#[cfg(feature = "archive")]
+fn first_rom_name<R: io::Read + io::Seek>(archive: &mut zip::ZipArchive<R>) -> io::Result<String> {
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+
+        if file.is_file() && is_rom_name(file.name()) {
+            return Ok(file.name().to_string());
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "no .gb or .gbc file in the zip archive"))
+}
+
+#[cfg(all(test, feature = "archive"))]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+
+        for (name, content) in files {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn plain_rom_untouched() {
+        assert_eq!(extract_rom(vec![0x00, 0xC3, 0x50], None).unwrap(), vec![0x00, 0xC3, 0x50]);
+    }
+
+    #[test]
+    fn zip_first_rom() {
+        let data = zip(&[("readme.txt", b"hello"), ("game.GB", b"rom"), ("other.gbc", b"other")]);
+
+        assert_eq!(extract_rom(data, None).unwrap(), b"rom");
+    }
+
+    #[test]
+    fn zip_named_entry() {
+        let data = zip(&[("game.gb", b"rom"), ("other.gbc", b"other")]);
+
+        assert_eq!(extract_rom(data.clone(), Some("other.gbc")).unwrap(), b"other");
+        assert!(extract_rom(data, Some("missing.gb")).is_err());
+    }
+
+    #[test]
+    fn zip_without_rom() {
+        let data = zip(&[("readme.txt", b"hello")]);
+
+        assert_eq!(extract_rom(data, None).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn gzip_rom() {
+        assert_eq!(extract_rom(gzip(b"rom"), None).unwrap(), b"rom");
+    }
+
+    #[test]
+    fn size_limit() {
+        let rom = vec![0; MAX_ROM_SIZE];
+        let oversized = vec![0; MAX_ROM_SIZE + 1];
+
+        assert_eq!(extract_rom(gzip(&rom), None).unwrap().len(), MAX_ROM_SIZE);
+        assert_eq!(extract_rom(gzip(&oversized), None).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(extract_rom(zip(&[("game.gb", &rom)]), None).unwrap().len(), MAX_ROM_SIZE);
+        assert_eq!(
+            extract_rom(zip(&[("game.gb", &oversized)]), None).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use std::fs;
+use std::io;
+use std::path;
+
+/* Size of the picture taken by the M64282FP sensor of the Pocket Camera. */
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+
+/* Size of the developed picture, 16x14 tiles of 16 bytes. */
+pub const PICTURE_SIZE: usize = SENSOR_WIDTH * SENSOR_HEIGHT / 4;
+
+/* Control register, the trigger and output selection, followed by the sensor registers and the
+ * 4x4 dithering matrix of 3 thresholds per pixel.
+ */
+const REGISTER_COUNT: usize = 0x36;
+const MATRIX_START: usize = 0x06;
+
+/* Bit 0 of the control register starts a capture when set, and reads as 1 until it's done. */
+const CAPTURE: u8 = 0x01;
+
+/* Edge enhancement ratio selected by register 4 bits 4-6, in quarters. */
+const EDGE_RATIOS: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+
+/* Where the camera sensor gets its picture from. */
+pub trait ImageSource {
+    /* A SENSOR_WIDTH x SENSOR_HEIGHT grayscale image, row by row, 0 being black and 255 white. */
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+/* Always the same picture: a gradient going from black to white over the top half, and a
+ * checkerboard of 8x8 squares over the bottom one.
+ */
+pub struct TestPattern;
+
+impl ImageSource for TestPattern {
+    fn capture(&mut self) -> Vec<u8> {
+        let mut image = vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+
+        for (i, pixel) in image.iter_mut().enumerate() {
+            let (x, y) = (i % SENSOR_WIDTH, i / SENSOR_WIDTH);
+
+            *pixel = match y < SENSOR_HEIGHT / 2 {
+                true => (x * 255 / (SENSOR_WIDTH - 1)) as u8,
+                false if (x / 8 + y / 8) % 2 == 0 => 0xFF,
+                false => 0x00,
+            };
+        }
+
+        image
+    }
+}
+
+/* A still picture, stretched to the sensor size. */
+pub struct StillImage {
+    image: Vec<u8>,
+}
+
+impl StillImage {
+    /* Reads a Netpbm image: PGM or PPM, plain or binary. */
+    pub fn open(path: &path::Path) -> io::Result<StillImage> {
+        StillImage::from_netpbm(&fs::read(path)?)
+    }
+
+    pub fn from_netpbm(data: &[u8]) -> io::Result<StillImage> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid Netpbm image: {}", msg));
+
+        let mut pos = 0;
+        let mut fields = Vec::new();
+
+        /* Magic, width, height and maximum value, separated by whitespace and comments. */
+        while fields.len() < 4 {
+            match data.get(pos) {
+                Some(b'#') => {
+                    while matches!(data.get(pos), Some(&c) if c != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => pos += 1,
+                Some(_) => {
+                    let start = pos;
+
+                    while matches!(data.get(pos), Some(c) if !c.is_ascii_whitespace()) {
+                        pos += 1;
+                    }
+
+                    fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+                }
+                None => return Err(invalid("truncated header")),
+            }
+        }
+
+        let (channels, binary) = match fields[0].as_str() {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => return Err(invalid("not a PGM or PPM image")),
+        };
+
+        let number = |field: &str| field.parse::<usize>().map_err(|_| invalid("bad header value"));
+        let width = number(&fields[1])?;
+        let height = number(&fields[2])?;
+        let max_value = number(&fields[3])?;
+
+        if width == 0 || height == 0 || max_value == 0 || max_value > 0xFFFF {
+            return Err(invalid("bad header value"));
+        }
+
+        let sample_count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| invalid("bad header value"))?;
+
+        /* A single whitespace separates the header from a binary raster. */
+        let raster = data.get(pos + 1..).unwrap_or(&[]);
+
+        let samples: Vec<usize> = match binary {
+            true if max_value > 0xFF => raster
+                .chunks_exact(2)
+                .map(|bytes| (bytes[0] as usize) << 8 | bytes[1] as usize)
+                .collect(),
+            true => raster.iter().map(|&byte| byte as usize).collect(),
+            false => String::from_utf8_lossy(&data[pos..])
+                .split_ascii_whitespace()
+                .map(number)
+                .collect::<io::Result<_>>()?,
+        };
+
+        if samples.len() < sample_count {
+            return Err(invalid("truncated raster"));
+        }
+
+        let luminance = |pixel: usize| {
+            let sample = |channel: usize| samples[pixel * channels + channel].min(max_value) * 255 / max_value;
+
+            match channels {
+                1 => sample(0),
+                _ => (299 * sample(0) + 587 * sample(1) + 114 * sample(2)) / 1000,
+            }
+        };
+
+        let mut image = vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+
+        for (i, pixel) in image.iter_mut().enumerate() {
+            let x = i % SENSOR_WIDTH * width / SENSOR_WIDTH;
+            let y = i / SENSOR_WIDTH * height / SENSOR_HEIGHT;
+
+            *pixel = luminance(y * width + x) as u8;
+        }
+
+        Ok(StillImage { image })
+    }
+}
+
+impl ImageSource for StillImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.image.clone()
+    }
+}
+
+/* The Mitsubishi M64282FP sensor along with the cartridge logic developing its output into tiles.
+ * The analog side is approximated: gain, output voltage and zero point calibration are ignored.
+ */
+pub struct Camera {
+    registers: [u8; REGISTER_COUNT],
+    source: Box<dyn ImageSource>,
+    /* Cycles left until the picture being taken is ready, 0 when idle. */
+    busy_cycles: usize,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            registers: [0; REGISTER_COUNT],
+            source: Box::new(TestPattern),
+            busy_cycles: 0,
+        }
+    }
+
+    pub fn set_source(&mut self, source: Box<dyn ImageSource>) {
+        self.source = source;
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy_cycles > 0
+    }
+
+    /* Only the control register can be read back, the others read as 0. */
+    pub fn read_reg(&self, reg: usize) -> u8 {
+        match reg {
+            0 => (self.registers[0] & !CAPTURE) | self.is_busy() as u8,
+            _ => 0x00,
+        }
+    }
+
+    pub fn write_reg(&mut self, reg: usize, val: u8) {
+        match reg {
+            0 => {
+                /* A capture can't be aborted. */
+                if val & CAPTURE != 0 && !self.is_busy() {
+                    self.busy_cycles = self.capture_cycles();
+                }
+
+                self.registers[0] = val & 0x07;
+            }
+            1..=0x35 => self.registers[reg] = val,
+            _ => {}
+        }
+    }
+
+    /* The capture takes 32446 M-cycles, 512 more without N, plus 16 per exposure step. */
+    fn capture_cycles(&self) -> usize {
+        let n = self.registers[1] & 0x80 != 0;
+        let m_cycles = 32446 + if n { 0 } else { 512 } + 16 * self.exposure();
+
+        4 * m_cycles
+    }
+
+    fn exposure(&self) -> usize {
+        (self.registers[2] as usize) << 8 | self.registers[3] as usize
+    }
+
+    /* Returns true once the picture is ready to be developed. */
+    pub fn do_cycle(&mut self) -> bool {
+        if !self.is_busy() {
+            return false;
+        }
+
+        self.busy_cycles -= 1;
+        self.busy_cycles == 0
+    }
+
+    /* Takes a picture from the source and writes it as PICTURE_SIZE bytes of tile data. */
+    pub fn develop(&mut self, tiles: &mut [u8]) {
+        let mut image = self.source.capture();
+        image.resize(SENSOR_WIDTH * SENSOR_HEIGHT, 0);
+
+        let exposure = self.exposure() as i32;
+        let invert = self.registers[4] & 0x08 != 0;
+
+        /* Exposure, then the output range of the sensor, signed around the middle value. */
+        let mut pixels: Vec<i32> = image
+            .iter()
+            .map(|&pixel| {
+                let value = (pixel as i32 * exposure / 0x300).min(255);
+                let value = (128 + (value - 128) / 8).clamp(0, 255);
+
+                match invert {
+                    true => 127 - value,
+                    false => value - 128,
+                }
+            })
+            .collect();
+
+        pixels = self.enhance_edges(&pixels);
+
+        for tile in tiles.iter_mut().take(PICTURE_SIZE) {
+            *tile = 0;
+        }
+
+        for (i, &value) in pixels.iter().enumerate() {
+            let (x, y) = (i % SENSOR_WIDTH, i / SENSOR_WIDTH);
+            let color = self.dither(value + 128, x, y);
+            let offset = (y / 8 * (SENSOR_WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+            let bit = 7 - (x % 8);
+
+            tiles[offset] |= (color & 0x01) << bit;
+            tiles[offset + 1] |= (color >> 1) << bit;
+        }
+    }
+
+    /* Filtering mode from the N, VH and E3 bits: plain output, horizontal edge enhancement or 2D
+     * edge enhancement, followed in the first two cases by the 1D filter selected by the control
+     * register.
+     */
+    fn enhance_edges(&self, pixels: &[i32]) -> Vec<i32> {
+        let n = (self.registers[1] >> 7) & 0x01;
+        let vh = (self.registers[1] >> 5) & 0x03;
+        let e3 = (self.registers[4] >> 7) & 0x01;
+        let ratio = EDGE_RATIOS[((self.registers[4] >> 4) & 0x07) as usize];
+
+        let at = |x: usize, y: usize| pixels[y * SENSOR_WIDTH + x];
+        let neighbours = |x: usize, y: usize| {
+            (
+                at(x.saturating_sub(1), y),
+                at((x + 1).min(SENSOR_WIDTH - 1), y),
+                at(x, y.saturating_sub(1)),
+                at(x, (y + 1).min(SENSOR_HEIGHT - 1)),
+            )
+        };
+
+        let mut output = pixels.to_vec();
+
+        match n << 3 | vh << 1 | e3 {
+            0x0 => output = self.filter(&output),
+            0x2 => {
+                for (i, pixel) in output.iter_mut().enumerate() {
+                    let (x, y) = (i % SENSOR_WIDTH, i / SENSOR_WIDTH);
+                    let (west, east, _, _) = neighbours(x, y);
+                    let p = at(x, y);
+
+                    *pixel = (p + (2 * p - west - east) * ratio / 4).clamp(-128, 127);
+                }
+
+                output = self.filter(&output);
+            }
+            0xE => {
+                for (i, pixel) in output.iter_mut().enumerate() {
+                    let (x, y) = (i % SENSOR_WIDTH, i / SENSOR_WIDTH);
+                    let (west, east, north, south) = neighbours(x, y);
+                    let p = at(x, y);
+
+                    *pixel = (p + (4 * p - west - east - north - south) * ratio / 4).clamp(-128, 127);
+                }
+            }
+            _ => {}
+        }
+
+        output
+    }
+
+    /* Control register bits 1-2 pick what gets added (P) and subtracted (M): the pixel itself
+     * and/or the one below it.
+     */
+    fn filter(&self, pixels: &[i32]) -> Vec<i32> {
+        let (p, m) = match (self.registers[0] >> 1) & 0x03 {
+            0 => (0b00, 0b01),
+            1 => (0b01, 0b00),
+            _ => (0b01, 0b10),
+        };
+
+        pixels
+            .iter()
+            .enumerate()
+            .map(|(i, &pixel)| {
+                /* The last line has nothing below, it repeats itself. */
+                let below = match pixels.get(i + SENSOR_WIDTH) {
+                    Some(&below) => below,
+                    None => pixel,
+                };
+                let mut value = 0;
+
+                if p & 0b01 != 0 {
+                    value += pixel;
+                }
+                if p & 0b10 != 0 {
+                    value += below;
+                }
+                if m & 0b01 != 0 {
+                    value -= pixel;
+                }
+                if m & 0b10 != 0 {
+                    value -= below;
+                }
+
+                value.clamp(-128, 127)
+            })
+            .collect()
+    }
+
+    /* Compares the value with the 3 thresholds of the matrix for this pixel, below the first one
+     * is black.
+     */
+    fn dither(&self, value: i32, x: usize, y: usize) -> u8 {
+        let base = MATRIX_START + ((y % 4) * 4 + x % 4) * 3;
+        let thresholds = &self.registers[base..base + 3];
+
+        3 - thresholds.iter().filter(|&&threshold| value >= threshold as i32).count() as u8
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /* Reads back the color of a pixel from the developed tile data. */
+    fn color(tiles: &[u8], x: usize, y: usize) -> u8 {
+        let offset = (y / 8 * (SENSOR_WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+        let bit = 7 - (x % 8);
+
+        (tiles[offset] >> bit & 0x01) | (tiles[offset + 1] >> bit & 0x01) << 1
+    }
+
+    fn camera(exposure: u16, thresholds: [u8; 3]) -> Camera {
+        let mut camera = Camera::new();
+
+        /* Positive output, no edge enhancement. */
+        camera.write_reg(2, (exposure >> 8) as u8);
+        camera.write_reg(3, exposure as u8);
+        for reg in 0..16 {
+            for (i, &threshold) in thresholds.iter().enumerate() {
+                camera.write_reg(MATRIX_START + reg * 3 + i, threshold);
+            }
+        }
+
+        camera
+    }
+
+    #[test]
+    fn capture_takes_time() {
+        let mut camera = camera(0x0300, [0x80; 3]);
+
+        camera.write_reg(0, 0x03);
+        assert_eq!(camera.read_reg(0), 0x03);
+
+        let mut cycles = 1;
+        while !camera.do_cycle() {
+            cycles += 1;
+        }
+
+        assert_eq!(cycles, 4 * (32446 + 512 + 16 * 0x0300));
+        assert_eq!(camera.read_reg(0), 0x02);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let mut camera = camera(0x0300, [0x78, 0x80, 0x88]);
+        camera.write_reg(0, 0x02);
+
+        let mut tiles = vec![0xAA; PICTURE_SIZE];
+        camera.develop(&mut tiles);
+
+        /* Gradient from black to white. */
+        assert_eq!(color(&tiles, 0, 0), 3);
+        assert_eq!(color(&tiles, 64, 0), 1);
+        assert_eq!(color(&tiles, 127, 0), 0);
+
+        /* Checkerboard. */
+        assert_eq!(color(&tiles, 0, 64), 0);
+        assert_eq!(color(&tiles, 8, 64), 3);
+
+        /* Negative output. */
+        camera.write_reg(0, 0x00);
+        camera.develop(&mut tiles);
+        assert_eq!(color(&tiles, 0, 0), 0);
+        assert_eq!(color(&tiles, 127, 0), 3);
+    }
+
+    #[test]
+    fn netpbm() {
+        let image = StillImage::from_netpbm(b"P2\n# 2x1\n2 1\n15\n0 15\n").unwrap();
+        assert_eq!(image.image[0], 0x00);
+        assert_eq!(image.image[SENSOR_WIDTH - 1], 0xFF);
+
+        let mut data = b"P6 1 1 255\n".to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
+        assert_eq!(StillImage::from_netpbm(&data).unwrap().image[0], 0xFF);
+
+        assert!(StillImage::from_netpbm(b"P5 2 2 255\n\x00").is_err());
+        assert!(StillImage::from_netpbm(b"P6 4294967296 4294967296 255\n\x00").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: gameboy-rs [OPTIONS] <ROM>
+
+The ROM can be a .gb/.gbc file, or a .zip or .gz archive containing one.
+
+Options:
+    --entry <NAME>      File to load from a zip archive (default: the first .gb/.gbc one)
+    --patch <FILE>      IPS, BPS or UPS patch to apply to the ROM
+                        (default: <ROM>.ips, <ROM>.bps or <ROM>.ups if there is one)
+    --no-patch          Don't look for a patch next to the ROM
+    --boot-rom <FILE>   Run this 256 bytes boot ROM instead of the built-in DMG one
+    --skip-boot         Start the cartridge directly, without running a boot ROM
+    --scale <N>         Window scale factor: 1, 2, 4, 8, 16 or 32 (default: 2)
+    --headless          Run without opening a window
+    --frames <N>        Exit after running N frames
+    --trace <FILE>      Write every executed instruction to FILE
+    --speed <X>         Emulation speed multiplier, 0 runs as fast as possible
+                        (default: 1, or 0 when headless)
+    --save-dir <DIR>    Directory where battery backed saves are stored
+                        (default: next to the ROM)
+    --rtc-sync          Advance the cartridge clock by the real time elapsed since
+                        the last save
+    --camera-image <FILE>
+                        PGM or PPM picture seen by the Pocket Camera sensor
+                        (default: a test pattern)
+    --info              Print the cartridge header and exit
+    -h, --help          Print this help and exit";
+
+pub struct Options {
+    pub rom_path: PathBuf,
+    pub entry: Option<String>,
+    pub patch: Option<PathBuf>,
+    pub no_patch: bool,
+    pub boot_rom: Option<PathBuf>,
+    pub skip_boot: bool,
+    #[cfg_attr(not(feature = "window"), allow(dead_code))]
+    pub scale: usize,
+    pub headless: bool,
+    pub frames: Option<usize>,
+    pub trace: Option<PathBuf>,
+    pub speed: f64,
+    pub save_dir: Option<PathBuf>,
+    pub rtc_sync: bool,
+    pub camera_image: Option<PathBuf>,
+    pub info: bool,
+}
+
+/* Returns None when the help was asked for. */
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut rom_path = None;
+    let mut entry = None;
+    let mut patch = None;
+    let mut no_patch = false;
+    let mut boot_rom = None;
+    let mut skip_boot = false;
+    let mut scale = 2;
+    let mut headless = false;
+    let mut frames = None;
+    let mut trace = None;
+    let mut speed = None;
+    let mut save_dir = None;
+    let mut rtc_sync = false;
+    let mut camera_image = None;
+    let mut info = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--entry" => entry = Some(value(&mut args, &arg)?),
+            "--patch" => patch = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--no-patch" => no_patch = true,
+            "--boot-rom" => boot_rom = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--skip-boot" => skip_boot = true,
+            "--scale" => {
+                scale = number(&value(&mut args, &arg)?, &arg)?;
+
+                if ![1, 2, 4, 8, 16, 32].contains(&scale) {
+                    return Err(format!("invalid value for --scale: {}, expected 1, 2, 4, 8, 16 or 32", scale));
+                }
+            }
+            "--headless" => headless = true,
+            "--frames" => frames = Some(number(&value(&mut args, &arg)?, &arg)?),
+            "--trace" => trace = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--speed" => {
+                let val = value(&mut args, &arg)?;
+
+                match val.parse::<f64>() {
+                    Ok(x) if x >= 0.0 && x.is_finite() => speed = Some(x),
+                    _ => return Err(format!("invalid value for --speed: {}", val)),
+                }
+            }
+            "--save-dir" => save_dir = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--rtc-sync" => rtc_sync = true,
+            "--camera-image" => camera_image = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--info" => info = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if rom_path.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => rom_path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    if patch.is_some() && no_patch {
+        return Err(String::from("--patch and --no-patch can't be used together"));
+    }
+
+    if boot_rom.is_some() && skip_boot {
+        return Err(String::from("--boot-rom and --skip-boot can't be used together"));
+    }
+
+    Ok(Some(Options {
+        rom_path: rom_path.ok_or_else(|| String::from("missing ROM path"))?,
+        entry,
+        patch,
+        no_patch,
+        boot_rom,
+        skip_boot,
+        scale,
+        headless,
+        frames,
+        trace,
+        speed: speed.unwrap_or(if headless { 0.0 } else { 1.0 }),
+        save_dir,
+        rtc_sync,
+        camera_image,
+        info,
+    }))
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("missing value for {}", option))
+}
+
+fn number(val: &str, option: &str) -> Result<usize, String> {
+    val.parse().map_err(|_| format!("invalid value for {}: {}", option, val))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Option<Options>, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        parse_args(args).err().unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse_args(&["game.gb"]).unwrap().unwrap();
+
+        assert_eq!(options.rom_path, PathBuf::from("game.gb"));
+        assert_eq!(options.scale, 2);
+        assert_eq!(options.speed, 1.0);
+        assert_eq!(options.frames, None);
+        assert!(!options.headless);
+
+        /* Headless runs go as fast as possible unless asked otherwise. */
+        assert_eq!(parse_args(&["--headless", "game.gb"]).unwrap().unwrap().speed, 0.0);
+        assert_eq!(parse_args(&["--headless", "--speed", "2", "game.gb"]).unwrap().unwrap().speed, 2.0);
+    }
+
+    #[test]
+    fn help() {
+        assert!(parse_args(&["game.gb", "--help"]).unwrap().is_none());
+        assert!(parse_args(&["-h"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn missing_values() {
+        assert_eq!(parse_error(&[]), "missing ROM path");
+        assert_eq!(parse_error(&["--headless"]), "missing ROM path");
+        assert_eq!(parse_error(&["game.gb", "--scale"]), "missing value for --scale");
+        assert_eq!(parse_error(&["game.gb", "--patch"]), "missing value for --patch");
+    }
+
+    #[test]
+    fn unexpected_arguments() {
+        assert_eq!(parse_error(&["game.gb", "--turbo"]), "unknown option --turbo");
+        assert_eq!(parse_error(&["game.gb", "other.gb"]), "unexpected argument other.gb");
+    }
+
+    #[test]
+    fn conflicting_options() {
+        assert_eq!(
+            parse_error(&["--patch", "fix.ips", "--no-patch", "game.gb"]),
+            "--patch and --no-patch can't be used together"
+        );
+        assert_eq!(
+            parse_error(&["--boot-rom", "dmg.bin", "--skip-boot", "game.gb"]),
+            "--boot-rom and --skip-boot can't be used together"
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(
+            parse_error(&["--scale", "3", "game.gb"]),
+            "invalid value for --scale: 3, expected 1, 2, 4, 8, 16 or 32"
+        );
+        assert_eq!(parse_error(&["--scale", "big", "game.gb"]), "invalid value for --scale: big");
+        assert_eq!(parse_error(&["--frames", "-1", "game.gb"]), "invalid value for --frames: -1");
+        assert_eq!(parse_error(&["--speed", "-2", "game.gb"]), "invalid value for --speed: -2");
+        assert_eq!(parse_error(&["--speed", "inf", "game.gb"]), "invalid value for --speed: inf");
+    }
+}
@@ -1,6 +1,11 @@
-use std::path;
+use std::io::Write;
 
+use crate::camera::Camera;
 use crate::decode;
+use crate::framebuffer::Framebuffer;
+use crate::infrared::Infrared;
+use crate::joypad::Buttons;
+use crate::mbc::MBC;
 use crate::mmu::MMU;
 use crate::registers::{CpuFlag, Registers};
 
@@ -13,73 +18,250 @@ pub struct CPU {
     cycles_remaining: u8,
 
     ime: bool,
+    /* EI only enables interrupts after the instruction following it. */
+    ime_scheduled: bool,
+
+    /* Low power states. */
+    halted: bool,
+    halt_bug: bool,
+    stopped: bool,
+    /* Set by the illegal opcodes, nothing but a reset gets the CPU out of there. */
+    locked: bool,
+
+    /* Every executed instruction gets logged there when set. */
+    trace: Option<Box<dyn Write>>,
 }
 
 impl CPU {
-    pub fn new(path: &path::Path) -> CPU {
+    pub fn new(mbc: Box<dyn MBC>) -> CPU {
         CPU {
             registers: Registers::new(),
-            mmu: MMU::new(path),
+            mmu: MMU::new(mbc),
 
             total_cycles: 0,
             cycles_remaining: 0,
 
             ime: false,
+            ime_scheduled: false,
+
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+
+            trace: None,
         }
     }
 
-    fn print_regs(&self) {
-        println!("AF {:04X}", self.registers.get_af());
-        println!("BC {:04X}", self.registers.get_bc());
-        println!("DE {:04X}", self.registers.get_de());
-        println!("HL {:04X}", self.registers.get_hl());
-        println!("SP {:04X}", self.registers.sp);
-        println!("PC {:04X}", self.registers.pc);
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.mmu.set_boot_rom(boot_rom);
     }
 
-    fn debug_dump(&self) {
-        println!("\n----- DEBUG DUMP -----");
-        self.print_regs();
+    /* Starts at the cartridge entry point, in the state the DMG boot ROM leaves the CPU in. */
+    pub fn skip_boot_rom(&mut self) {
+        self.registers.set_af(0x01B0);
+        self.registers.set_bc(0x0013);
+        self.registers.set_de(0x00D8);
+        self.registers.set_hl(0x014D);
+        self.registers.sp = 0xFFFE;
+        self.registers.pc = 0x0100;
 
-        println!(
-            "total_cycles: {} cycles_remaining: {}",
-            self.total_cycles, self.cycles_remaining
-        );
+        self.mmu.skip_boot_rom();
+    }
+
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
     }
 
     pub fn do_cycle(&mut self) {
-        if self.should_load_next_instr() {
-            if self.mmu.is_dmg_disabled() {
-                if self.registers.pc == 0x40 {
-                eprintln!("Breakpoint at 0x40");
-                loop {}
-                }
+        if self.stopped {
+            /* The system clock, and so the PPU and timer, stay stopped until a joypad line goes
+             * low.
+             */
+            if !self.mmu.is_joypad_line_low() {
+                return;
             }
 
-            let op = self.fetch_next_opcode();
-
-            println!(
-                "op {:2X} pc = {:4X}: \t {}",
-                op,
-                self.registers.pc,
-                decode::decode_instruction(op, &self.mmu, &self.registers)
-            );
+            self.stopped = false;
+        }
 
-            /* Go past opcode byte */
-            self.registers.pc += 1;
+        if self.should_load_next_instr() {
+            /* HALT is exited as soon as an interrupt is pending, even if IME is not set. */
+            if self.halted && self.mmu.has_pending_interrupt() {
+                self.halted = false;
+            }
 
-            self.cycles_remaining += self.execute_instruction(op);
+            self.cycles_remaining += if self.halted || self.locked {
+                /* No instruction is fetched, but the rest of the system keeps running. */
+                4
+            } else {
+                match self.service_interrupt() {
+                    Some(cycles) => cycles,
+                    None => self.execute_next_instruction(),
+                }
+            };
 
             self.total_cycles += self.cycles_remaining as usize;
-        } else {
-            /* TODO: do something with timing */
-            self.cycles_remaining -= 1;
         }
 
+        /* The cycle we are in right now counts towards the current instruction. */
+        self.cycles_remaining -= 1;
+
         // /* TODO: the ppu shouldn't cycle as fast as the cpu */
         self.mmu.do_cycle();
     }
 
+    /* Runs until the current instruction is done and returns the number of cycles it took. */
+    pub fn step_instruction(&mut self) -> usize {
+        let start = self.total_cycles;
+
+        loop {
+            self.do_cycle();
+
+            /* Nothing runs at all while stopped, return to let the caller feed new input. */
+            if self.should_load_next_instr() || self.stopped {
+                break;
+            }
+        }
+
+        self.total_cycles - start
+    }
+
+    fn execute_next_instruction(&mut self) -> u8 {
+        let op = self.fetch_next_opcode();
+
+        if self.trace.is_some() {
+            self.trace_instruction(op);
+        }
+
+        /* Go past opcode byte */
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
+
+        /* Only an EI executed before this instruction takes effect once it is done. */
+        let enable_ime = self.ime_scheduled;
+
+        let cycles = self.execute_instruction(op);
+
+        if enable_ime && self.ime_scheduled {
+            self.ime_scheduled = false;
+            self.ime = true;
+        }
+
+        cycles
+    }
+
+    fn trace_instruction(&mut self, op: u8) {
+        let regs = &self.registers;
+        let line = format!(
+            "PC:{:04X} AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X} | {:02X} {}",
+            regs.pc,
+            regs.get_af(),
+            regs.get_bc(),
+            regs.get_de(),
+            regs.get_hl(),
+            regs.sp,
+            op,
+            decode::decode_instruction(op, &self.mmu, regs)
+        );
+
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(err) = writeln!(trace, "{}", line) {
+                eprintln!("Failed to write trace, disabling it: {}", err);
+                self.trace = None;
+            }
+        }
+    }
+
+    /* Jumps to the vector of the highest priority pending interrupt, if interrupts are enabled. */
+    fn service_interrupt(&mut self) -> Option<u8> {
+        if !self.ime {
+            return None;
+        }
+
+        let interrupt = self.mmu.next_pending_interrupt()?;
+
+        self.ime = false;
+        self.mmu.clear_interrupt(interrupt);
+
+        self.push_word(self.registers.pc);
+        self.registers.pc = interrupt.vector();
+
+        Some(20)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn read_memory(&self, addr: u16) -> u8 {
+        self.mmu.read(addr)
+    }
+
+    pub fn write_memory(&mut self, addr: u16, value: u8) {
+        self.mmu.write(addr, value);
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.mmu.set_buttons(buttons);
+    }
+
+    pub fn sync_rtc_to_host(&mut self) {
+        self.mmu.sync_rtc_to_host();
+    }
+
+    pub fn save_data(&self) -> Vec<u8> {
+        self.mmu.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mmu.load_save_data(data);
+    }
+
+    pub fn take_save_dirty(&mut self) -> bool {
+        self.mmu.take_save_dirty()
+    }
+
+    pub fn infrared(&mut self) -> Option<&mut Infrared> {
+        self.mmu.infrared()
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.mmu.set_tilt(x, y);
+    }
+
+    pub fn camera(&mut self) -> Option<&mut Camera> {
+        self.mmu.camera()
+    }
+
+    pub fn is_rumbling(&self) -> bool {
+        self.mmu.is_rumbling()
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.mmu.framebuffer()
+    }
+
+    /* Returns true, only once, when the PPU finished rendering a new frame. */
+    pub fn take_frame_ready(&mut self) -> bool {
+        self.mmu.take_frame_ready()
+    }
+
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.mmu.take_serial_output()
+    }
+
     fn should_load_next_instr(&self) -> bool {
         self.cycles_remaining == 0
     }
@@ -92,6 +274,7 @@ impl CPU {
         match opcode {
             0x00 => {
                 /* NOP */
+
                 4
             }
             0x01 => {
@@ -102,80 +285,140 @@ impl CPU {
                 12
             }
             0x02 => {
-                /* LD (BC), A */
+                /* LD (BC),A */
                 let addr = self.registers.get_bc();
                 self.mmu.write(addr, self.registers.a);
 
                 8
             }
+            0x03 => {
+                /* INC BC */
+                let bc = self.registers.get_bc();
+                self.registers.set_bc(bc.wrapping_add(1));
+
+                8
+            }
             0x04 => {
                 /* INC B */
-                self.registers.b = self.alu8_inc(self.registers.b);
+                self.registers.b = self.registers.alu8_inc(self.registers.b);
 
                 4
             }
             0x05 => {
                 /* DEC B */
-                self.registers.b = self.alu8_dec(self.registers.b);
+                self.registers.b = self.registers.alu8_dec(self.registers.b);
 
                 4
             }
             0x06 => {
-                /* LD B, d8 */
+                /* LD B,d8 */
                 self.registers.b = self.fetch_imm8();
 
                 8
             }
+            0x07 => {
+                /* RLCA */
+                self.registers.a = self.registers.alu8_rlc(self.registers.a);
+                self.registers.set_flag(CpuFlag::Z, false);
+
+                4
+            }
+            0x08 => {
+                /* LD (a16),SP */
+                let addr = self.fetch_imm16();
+                self.mmu.write_wide(addr, self.registers.sp);
+
+                20
+            }
+            0x09 => {
+                /* ADD HL,BC */
+                let res = self.registers.alu16_add(self.registers.get_hl(), self.registers.get_bc());
+                self.registers.set_hl(res);
+
+                8
+            }
+            0x0A => {
+                /* LD A,(BC) */
+                self.registers.a = self.mmu.read(self.registers.get_bc());
+
+                8
+            }
             0x0B => {
                 /* DEC BC */
                 let bc = self.registers.get_bc();
-                self.registers.set_bc(bc - 1);
+                self.registers.set_bc(bc.wrapping_sub(1));
 
                 8
             }
             0x0C => {
                 /* INC C */
-                self.registers.c = self.alu8_inc(self.registers.c);
+                self.registers.c = self.registers.alu8_inc(self.registers.c);
 
                 4
             }
             0x0D => {
                 /* DEC C */
-                self.registers.c = self.alu8_dec(self.registers.c);
+                self.registers.c = self.registers.alu8_dec(self.registers.c);
 
                 4
             }
             0x0E => {
-                /* LD C, d8 */
+                /* LD C,d8 */
                 self.registers.c = self.fetch_imm8();
 
                 8
             }
+            0x0F => {
+                /* RRCA */
+                self.registers.a = self.registers.alu8_rrc(self.registers.a);
+                self.registers.set_flag(CpuFlag::Z, false);
+
+                4
+            }
+            0x10 => {
+                /* STOP */
+                /* STOP is followed by a padding byte which is skipped. */
+                self.fetch_imm8();
+
+                /* DIV is reset when entering STOP mode. */
+                self.mmu.write(0xFF04, 0);
+                self.stopped = true;
+
+                4
+            }
             0x11 => {
-                /* LD DE, imm16 */
-                let imm16 = self.fetch_imm16();
-                self.registers.set_de(imm16);
+                /* LD DE,d16 */
+                let d16 = self.fetch_imm16();
+                self.registers.set_de(d16);
 
                 12
             }
+            0x12 => {
+                /* LD (DE),A */
+                let addr = self.registers.get_de();
+                self.mmu.write(addr, self.registers.a);
+
+                8
+            }
             0x13 => {
+                /* INC DE */
                 let de = self.registers.get_de();
-                self.registers.set_de(de + 1);
+                self.registers.set_de(de.wrapping_add(1));
 
                 8
             }
             0x14 => {
                 /* INC D */
-                self.registers.d = self.alu8_inc(self.registers.d);
+                self.registers.d = self.registers.alu8_inc(self.registers.d);
 
                 4
             }
             0x15 => {
                 /* DEC D */
-                self.registers.d = self.alu8_dec(self.registers.d);
+                self.registers.d = self.registers.alu8_dec(self.registers.d);
 
                 4
-            },
+            }
             0x16 => {
                 /* LD D,d8 */
                 self.registers.d = self.fetch_imm8();
@@ -183,8 +426,9 @@ impl CPU {
                 8
             }
             0x17 => {
-                /* RLA A */
-                self.registers.a = self.alu8_rl(self.registers.a);
+                /* RLA */
+                self.registers.a = self.registers.alu8_rl(self.registers.a);
+                self.registers.set_flag(CpuFlag::Z, false);
 
                 4
             }
@@ -197,57 +441,54 @@ impl CPU {
             }
             0x19 => {
                 /* ADD HL,DE */
-                let res = self.alu16_add(self.registers.get_hl(), self.registers.get_de());
+                let res = self.registers.alu16_add(self.registers.get_hl(), self.registers.get_de());
                 self.registers.set_hl(res);
 
                 8
             }
             0x1A => {
                 /* LD A,(DE) */
-                let val = self.mmu.read(self.registers.get_de());
-                self.registers.a = val;
+                self.registers.a = self.mmu.read(self.registers.get_de());
+
+                8
+            }
+            0x1B => {
+                /* DEC DE */
+                let de = self.registers.get_de();
+                self.registers.set_de(de.wrapping_sub(1));
 
                 8
             }
             0x1C => {
                 /* INC E */
-                self.registers.e = self.alu8_inc(self.registers.e);
+                self.registers.e = self.registers.alu8_inc(self.registers.e);
 
                 4
             }
             0x1D => {
                 /* DEC E */
-                self.registers.e -= 1;
+                self.registers.e = self.registers.alu8_dec(self.registers.e);
 
                 4
             }
             0x1E => {
-                /* LD E, d8 */
+                /* LD E,d8 */
                 self.registers.e = self.fetch_imm8();
 
                 8
             }
             0x1F => {
                 /* RRA */
-                let carry = match self.registers.a & 1 {
-                    1 => true,
-                    _ => false,
-                };
-
-                self.registers.a >>= 1;
-
-                self.registers.set_flag(CpuFlag::Z, self.registers.a == 0);
-                self.registers.set_flag(CpuFlag::N, false);
-                self.registers.set_flag(CpuFlag::H, false);
-                self.registers.set_flag(CpuFlag::C, carry);
+                self.registers.a = self.registers.alu8_rr(self.registers.a);
+                self.registers.set_flag(CpuFlag::Z, false);
 
                 4
             }
             0x20 => {
-                /* JR NZ, r8 */
+                /* JR NZ,r8 */
                 let r8 = self.fetch_imm8() as i8;
 
-                if self.registers.get_flag(CpuFlag::Z) == false {
+                if !self.registers.get_flag(CpuFlag::Z) {
                     self.registers.pc = CPU::calc_rel_addr(self.registers.pc, r8);
                     12
                 } else {
@@ -255,9 +496,8 @@ impl CPU {
                 }
             }
             0x21 => {
-                /* LD HL, d16 */
+                /* LD HL,d16 */
                 let d16 = self.fetch_imm16();
-
                 self.registers.set_hl(d16);
 
                 12
@@ -266,30 +506,43 @@ impl CPU {
                 /* LD (HL+),A */
                 let hl = self.registers.get_hl();
                 self.mmu.write(hl, self.registers.a);
-                self.registers.set_hl(hl + 1);
+                self.registers.set_hl(hl.wrapping_add(1));
 
                 8
             }
             0x23 => {
                 /* INC HL */
-                self.registers.set_hl(self.registers.get_hl() + 1);
+                let hl = self.registers.get_hl();
+                self.registers.set_hl(hl.wrapping_add(1));
 
                 8
             }
             0x24 => {
                 /* INC H */
-                self.registers.h = self.alu8_inc(self.registers.h);
+                self.registers.h = self.registers.alu8_inc(self.registers.h);
 
                 4
             }
             0x25 => {
                 /* DEC H */
-                self.registers.h = self.alu8_dec(self.registers.h);
+                self.registers.h = self.registers.alu8_dec(self.registers.h);
+
+                4
+            }
+            0x26 => {
+                /* LD H,d8 */
+                self.registers.h = self.fetch_imm8();
+
+                8
+            }
+            0x27 => {
+                /* DAA */
+                self.registers.a = self.registers.alu8_daa(self.registers.a);
 
                 4
             }
             0x28 => {
-                /* JR Z, r8 */
+                /* JR Z,r8 */
                 let r8 = self.fetch_imm8() as i8;
 
                 if self.registers.get_flag(CpuFlag::Z) {
@@ -299,23 +552,37 @@ impl CPU {
                     8
                 }
             }
+            0x29 => {
+                /* ADD HL,HL */
+                let res = self.registers.alu16_add(self.registers.get_hl(), self.registers.get_hl());
+                self.registers.set_hl(res);
+
+                8
+            }
             0x2A => {
                 /* LD A,(HL+) */
                 let hl = self.registers.get_hl();
                 self.registers.a = self.mmu.read(hl);
-                self.registers.set_hl(hl + 1);
+                self.registers.set_hl(hl.wrapping_add(1));
+
+                8
+            }
+            0x2B => {
+                /* DEC HL */
+                let hl = self.registers.get_hl();
+                self.registers.set_hl(hl.wrapping_sub(1));
 
                 8
             }
             0x2C => {
                 /* INC L */
-                self.registers.l = self.alu8_inc(self.registers.l);
+                self.registers.l = self.registers.alu8_inc(self.registers.l);
 
                 4
             }
             0x2D => {
                 /* DEC L */
-                self.registers.l = self.alu8_dec(self.registers.l);
+                self.registers.l = self.registers.alu8_dec(self.registers.l);
 
                 4
             }
@@ -334,32 +601,52 @@ impl CPU {
 
                 4
             }
+            0x30 => {
+                /* JR NC,r8 */
+                let r8 = self.fetch_imm8() as i8;
+
+                if !self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = CPU::calc_rel_addr(self.registers.pc, r8);
+                    12
+                } else {
+                    8
+                }
+            }
             0x31 => {
-                /* LD SP, d16 */
+                /* LD SP,d16 */
                 self.registers.sp = self.fetch_imm16();
 
                 12
             }
             0x32 => {
-                /* LD [HL-], A */
-                let addr = self.registers.get_hl();
+                /* LD (HL-),A */
+                let hl = self.registers.get_hl();
+                self.mmu.write(hl, self.registers.a);
+                self.registers.set_hl(hl.wrapping_sub(1));
 
-                self.mmu.write(addr, self.registers.a);
-                self.registers.set_hl(addr - 1);
+                8
+            }
+            0x33 => {
+                /* INC SP */
+                self.registers.sp = self.registers.sp.wrapping_add(1);
 
                 8
             }
-            0x3C => {
-                /* INC A */
-                self.registers.a = self.alu8_inc(self.registers.a);
+            0x34 => {
+                /* INC (HL) */
+                let hl = self.registers.get_hl();
+                let res = self.registers.alu8_inc(self.mmu.read(hl));
+                self.mmu.write(hl, res);
 
-                4
+                12
             }
-            0x3D => {
-                /* DEC A */
-                self.registers.a = self.alu8_dec(self.registers.a);
+            0x35 => {
+                /* DEC (HL) */
+                let hl = self.registers.get_hl();
+                let res = self.registers.alu8_dec(self.mmu.read(hl));
+                self.mmu.write(hl, res);
 
-                4
+                12
             }
             0x36 => {
                 /* LD (HL),d8 */
@@ -368,195 +655,876 @@ impl CPU {
 
                 12
             }
-            0x3E => {
-                /* LD A,d8 */
-                self.registers.a = self.fetch_imm8();
+            0x37 => {
+                /* SCF */
+                self.registers.set_flag(CpuFlag::N, false);
+                self.registers.set_flag(CpuFlag::H, false);
+                self.registers.set_flag(CpuFlag::C, true);
 
-                8
+                4
             }
-            0x47 => {
-                /* LD B,A */
-                self.registers.b = self.registers.a;
+            0x38 => {
+                /* JR C,r8 */
+                let r8 = self.fetch_imm8() as i8;
 
-                4
+                if self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = CPU::calc_rel_addr(self.registers.pc, r8);
+                    12
+                } else {
+                    8
+                }
             }
-            0x4F => {
-                /* LD C,A */
-                self.registers.c = self.registers.a;
+            0x39 => {
+                /* ADD HL,SP */
+                let res = self.registers.alu16_add(self.registers.get_hl(), self.registers.sp);
+                self.registers.set_hl(res);
 
-                4
+                8
             }
-            0x56 => {
-                /* LD D,(HL) */
-                let val = self.mmu.read(self.registers.get_hl());
-                self.registers.d = val;
+            0x3A => {
+                /* LD A,(HL-) */
+                let hl = self.registers.get_hl();
+                self.registers.a = self.mmu.read(hl);
+                self.registers.set_hl(hl.wrapping_sub(1));
 
                 8
             }
-            0x57 => {
-                /* LD D,A */
-                self.registers.d = self.registers.a;
+            0x3B => {
+                /* DEC SP */
+                self.registers.sp = self.registers.sp.wrapping_sub(1);
 
-                4
+                8
             }
-            0x5E => {
-                /* LD E,H */
-                self.registers.e = self.registers.h;
+            0x3C => {
+                /* INC A */
+                self.registers.a = self.registers.alu8_inc(self.registers.a);
 
                 4
             }
-            0x5F => {
-                /* LD E,A */
-                self.registers.e = self.registers.a;
+            0x3D => {
+                /* DEC A */
+                self.registers.a = self.registers.alu8_dec(self.registers.a);
 
                 4
             }
-            0x67 => {
-                /* LD H,A */
-                self.registers.h = self.registers.a;
+            0x3E => {
+                /* LD A,d8 */
+                self.registers.a = self.fetch_imm8();
+
+                8
+            }
+            0x3F => {
+                /* CCF */
+                let carry = self.registers.get_flag(CpuFlag::C);
+
+                self.registers.set_flag(CpuFlag::N, false);
+                self.registers.set_flag(CpuFlag::H, false);
+                self.registers.set_flag(CpuFlag::C, !carry);
 
                 4
             }
-            0x77 => {
-                /* LD (HL),A */
-                self.mmu.write(self.registers.get_hl(), self.registers.a);
+            0x40 => {
+                /* LD B,B */
 
-                8
+                4
             }
-            0x78 => {
-                /* LD A,B */
-                self.registers.a = self.registers.b;
+            0x41 => {
+                /* LD B,C */
+                self.registers.b = self.registers.c;
 
                 4
             }
-            0x79 => {
-                /* LD A,C */
-                self.registers.a = self.registers.c;
+            0x42 => {
+                /* LD B,D */
+                self.registers.b = self.registers.d;
 
                 4
             }
-            0x7B => {
-                /* LD A, E */
-                self.registers.a = self.registers.e;
+            0x43 => {
+                /* LD B,E */
+                self.registers.b = self.registers.e;
 
                 4
             }
-            0x7D => {
-                /* LD A,L */
-                self.registers.a = self.registers.l;
+            0x44 => {
+                /* LD B,H */
+                self.registers.b = self.registers.h;
 
                 4
             }
-            0x7C => {
-                /* LD A,H */
-                self.registers.a = self.registers.h;
+            0x45 => {
+                /* LD B,L */
+                self.registers.b = self.registers.l;
 
                 4
             }
-            0x86 => {
-                /* ADD A,(HL) */
-                self.registers. a = self.alu8_add(self.registers.a, self.mmu.read(self.registers.get_hl()));
+            0x46 => {
+                /* LD B,(HL) */
+                self.registers.b = self.mmu.read(self.registers.get_hl());
 
                 8
             }
-            0x87 => {
-                /* ADD A,A */
-                self.registers.a = self.alu8_add(self.registers.a, self.registers.a);
+            0x47 => {
+                /* LD B,A */
+                self.registers.b = self.registers.a;
 
-                8
+                4
             }
-            0x90 => {
-                /* SUB B */
-                self.registers.a = self.alu8_sub(self.registers.a, self.registers.b);
+            0x48 => {
+                /* LD C,B */
+                self.registers.c = self.registers.b;
 
                 4
             }
-            0xA1 => {
-                /* AND C */
-                self.registers.a = self.alu8_and(self.registers.a, self.registers.c);
+            0x49 => {
+                /* LD C,C */
 
                 4
             }
-            0xA9 => {
-                /* XOR C */
-                self.registers.a = self.alu8_xor(self.registers.a, self.registers.c);
+            0x4A => {
+                /* LD C,D */
+                self.registers.c = self.registers.d;
 
                 4
             }
-            0xAF => {
-                /* XOR A */
-                self.registers.a = self.alu8_xor(self.registers.a, self.registers.a);
+            0x4B => {
+                /* LD C,E */
+                self.registers.c = self.registers.e;
 
                 4
             }
-            0xB0 => {
-                /* OR B */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.b);
+            0x4C => {
+                /* LD C,H */
+                self.registers.c = self.registers.h;
 
                 4
-            },
-            0xB1 => {
+            }
+            0x4D => {
+                /* LD C,L */
+                self.registers.c = self.registers.l;
+
+                4
+            }
+            0x4E => {
+                /* LD C,(HL) */
+                self.registers.c = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x4F => {
+                /* LD C,A */
+                self.registers.c = self.registers.a;
+
+                4
+            }
+            0x50 => {
+                /* LD D,B */
+                self.registers.d = self.registers.b;
+
+                4
+            }
+            0x51 => {
+                /* LD D,C */
+                self.registers.d = self.registers.c;
+
+                4
+            }
+            0x52 => {
+                /* LD D,D */
+
+                4
+            }
+            0x53 => {
+                /* LD D,E */
+                self.registers.d = self.registers.e;
+
+                4
+            }
+            0x54 => {
+                /* LD D,H */
+                self.registers.d = self.registers.h;
+
+                4
+            }
+            0x55 => {
+                /* LD D,L */
+                self.registers.d = self.registers.l;
+
+                4
+            }
+            0x56 => {
+                /* LD D,(HL) */
+                self.registers.d = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x57 => {
+                /* LD D,A */
+                self.registers.d = self.registers.a;
+
+                4
+            }
+            0x58 => {
+                /* LD E,B */
+                self.registers.e = self.registers.b;
+
+                4
+            }
+            0x59 => {
+                /* LD E,C */
+                self.registers.e = self.registers.c;
+
+                4
+            }
+            0x5A => {
+                /* LD E,D */
+                self.registers.e = self.registers.d;
+
+                4
+            }
+            0x5B => {
+                /* LD E,E */
+
+                4
+            }
+            0x5C => {
+                /* LD E,H */
+                self.registers.e = self.registers.h;
+
+                4
+            }
+            0x5D => {
+                /* LD E,L */
+                self.registers.e = self.registers.l;
+
+                4
+            }
+            0x5E => {
+                /* LD E,(HL) */
+                self.registers.e = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x5F => {
+                /* LD E,A */
+                self.registers.e = self.registers.a;
+
+                4
+            }
+            0x60 => {
+                /* LD H,B */
+                self.registers.h = self.registers.b;
+
+                4
+            }
+            0x61 => {
+                /* LD H,C */
+                self.registers.h = self.registers.c;
+
+                4
+            }
+            0x62 => {
+                /* LD H,D */
+                self.registers.h = self.registers.d;
+
+                4
+            }
+            0x63 => {
+                /* LD H,E */
+                self.registers.h = self.registers.e;
+
+                4
+            }
+            0x64 => {
+                /* LD H,H */
+
+                4
+            }
+            0x65 => {
+                /* LD H,L */
+                self.registers.h = self.registers.l;
+
+                4
+            }
+            0x66 => {
+                /* LD H,(HL) */
+                self.registers.h = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x67 => {
+                /* LD H,A */
+                self.registers.h = self.registers.a;
+
+                4
+            }
+            0x68 => {
+                /* LD L,B */
+                self.registers.l = self.registers.b;
+
+                4
+            }
+            0x69 => {
+                /* LD L,C */
+                self.registers.l = self.registers.c;
+
+                4
+            }
+            0x6A => {
+                /* LD L,D */
+                self.registers.l = self.registers.d;
+
+                4
+            }
+            0x6B => {
+                /* LD L,E */
+                self.registers.l = self.registers.e;
+
+                4
+            }
+            0x6C => {
+                /* LD L,H */
+                self.registers.l = self.registers.h;
+
+                4
+            }
+            0x6D => {
+                /* LD L,L */
+
+                4
+            }
+            0x6E => {
+                /* LD L,(HL) */
+                self.registers.l = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x6F => {
+                /* LD L,A */
+                self.registers.l = self.registers.a;
+
+                4
+            }
+            0x70 => {
+                /* LD (HL),B */
+                self.mmu.write(self.registers.get_hl(), self.registers.b);
+
+                8
+            }
+            0x71 => {
+                /* LD (HL),C */
+                self.mmu.write(self.registers.get_hl(), self.registers.c);
+
+                8
+            }
+            0x72 => {
+                /* LD (HL),D */
+                self.mmu.write(self.registers.get_hl(), self.registers.d);
+
+                8
+            }
+            0x73 => {
+                /* LD (HL),E */
+                self.mmu.write(self.registers.get_hl(), self.registers.e);
+
+                8
+            }
+            0x74 => {
+                /* LD (HL),H */
+                self.mmu.write(self.registers.get_hl(), self.registers.h);
+
+                8
+            }
+            0x75 => {
+                /* LD (HL),L */
+                self.mmu.write(self.registers.get_hl(), self.registers.l);
+
+                8
+            }
+            0x76 => {
+                /* HALT */
+                if !self.ime && self.mmu.has_pending_interrupt() {
+                    /* HALT bug: the CPU doesn't halt and fails to increment PC after fetching the
+                     * next opcode, which then gets executed twice.
+                     */
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
+                }
+
+                4
+            }
+            0x77 => {
+                /* LD (HL),A */
+                self.mmu.write(self.registers.get_hl(), self.registers.a);
+
+                8
+            }
+            0x78 => {
+                /* LD A,B */
+                self.registers.a = self.registers.b;
+
+                4
+            }
+            0x79 => {
+                /* LD A,C */
+                self.registers.a = self.registers.c;
+
+                4
+            }
+            0x7A => {
+                /* LD A,D */
+                self.registers.a = self.registers.d;
+
+                4
+            }
+            0x7B => {
+                /* LD A,E */
+                self.registers.a = self.registers.e;
+
+                4
+            }
+            0x7C => {
+                /* LD A,H */
+                self.registers.a = self.registers.h;
+
+                4
+            }
+            0x7D => {
+                /* LD A,L */
+                self.registers.a = self.registers.l;
+
+                4
+            }
+            0x7E => {
+                /* LD A,(HL) */
+                self.registers.a = self.mmu.read(self.registers.get_hl());
+
+                8
+            }
+            0x7F => {
+                /* LD A,A */
+
+                4
+            }
+            0x80 => {
+                /* ADD A,B */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.b);
+
+                4
+            }
+            0x81 => {
+                /* ADD A,C */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.c);
+
+                4
+            }
+            0x82 => {
+                /* ADD A,D */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.d);
+
+                4
+            }
+            0x83 => {
+                /* ADD A,E */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.e);
+
+                4
+            }
+            0x84 => {
+                /* ADD A,H */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.h);
+
+                4
+            }
+            0x85 => {
+                /* ADD A,L */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.l);
+
+                4
+            }
+            0x86 => {
+                /* ADD A,(HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_add(self.registers.a, val);
+
+                8
+            }
+            0x87 => {
+                /* ADD A,A */
+                self.registers.a = self.registers.alu8_add(self.registers.a, self.registers.a);
+
+                4
+            }
+            0x88 => {
+                /* ADC A,B */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.b);
+
+                4
+            }
+            0x89 => {
+                /* ADC A,C */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.c);
+
+                4
+            }
+            0x8A => {
+                /* ADC A,D */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.d);
+
+                4
+            }
+            0x8B => {
+                /* ADC A,E */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.e);
+
+                4
+            }
+            0x8C => {
+                /* ADC A,H */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.h);
+
+                4
+            }
+            0x8D => {
+                /* ADC A,L */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.l);
+
+                4
+            }
+            0x8E => {
+                /* ADC A,(HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_adc(self.registers.a, val);
+
+                8
+            }
+            0x8F => {
+                /* ADC A,A */
+                self.registers.a = self.registers.alu8_adc(self.registers.a, self.registers.a);
+
+                4
+            }
+            0x90 => {
+                /* SUB B */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.b);
+
+                4
+            }
+            0x91 => {
+                /* SUB C */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.c);
+
+                4
+            }
+            0x92 => {
+                /* SUB D */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.d);
+
+                4
+            }
+            0x93 => {
+                /* SUB E */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.e);
+
+                4
+            }
+            0x94 => {
+                /* SUB H */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.h);
+
+                4
+            }
+            0x95 => {
+                /* SUB L */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.l);
+
+                4
+            }
+            0x96 => {
+                /* SUB (HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_sub(self.registers.a, val);
+
+                8
+            }
+            0x97 => {
+                /* SUB A */
+                self.registers.a = self.registers.alu8_sub(self.registers.a, self.registers.a);
+
+                4
+            }
+            0x98 => {
+                /* SBC A,B */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.b);
+
+                4
+            }
+            0x99 => {
+                /* SBC A,C */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.c);
+
+                4
+            }
+            0x9A => {
+                /* SBC A,D */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.d);
+
+                4
+            }
+            0x9B => {
+                /* SBC A,E */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.e);
+
+                4
+            }
+            0x9C => {
+                /* SBC A,H */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.h);
+
+                4
+            }
+            0x9D => {
+                /* SBC A,L */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.l);
+
+                4
+            }
+            0x9E => {
+                /* SBC A,(HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, val);
+
+                8
+            }
+            0x9F => {
+                /* SBC A,A */
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, self.registers.a);
+
+                4
+            }
+            0xA0 => {
+                /* AND B */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.b);
+
+                4
+            }
+            0xA1 => {
+                /* AND C */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.c);
+
+                4
+            }
+            0xA2 => {
+                /* AND D */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.d);
+
+                4
+            }
+            0xA3 => {
+                /* AND E */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.e);
+
+                4
+            }
+            0xA4 => {
+                /* AND H */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.h);
+
+                4
+            }
+            0xA5 => {
+                /* AND L */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.l);
+
+                4
+            }
+            0xA6 => {
+                /* AND (HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_and(self.registers.a, val);
+
+                8
+            }
+            0xA7 => {
+                /* AND A */
+                self.registers.a = self.registers.alu8_and(self.registers.a, self.registers.a);
+
+                4
+            }
+            0xA8 => {
+                /* XOR B */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.b);
+
+                4
+            }
+            0xA9 => {
+                /* XOR C */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.c);
+
+                4
+            }
+            0xAA => {
+                /* XOR D */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.d);
+
+                4
+            }
+            0xAB => {
+                /* XOR E */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.e);
+
+                4
+            }
+            0xAC => {
+                /* XOR H */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.h);
+
+                4
+            }
+            0xAD => {
+                /* XOR L */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.l);
+
+                4
+            }
+            0xAE => {
+                /* XOR (HL) */
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.a = self.registers.alu8_xor(self.registers.a, val);
+
+                8
+            }
+            0xAF => {
+                /* XOR A */
+                self.registers.a = self.registers.alu8_xor(self.registers.a, self.registers.a);
+
+                4
+            }
+            0xB0 => {
+                /* OR B */
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.b);
+
+                4
+            }
+            0xB1 => {
                 /* OR C */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.c);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.c);
 
                 4
             }
             0xB2 => {
                 /* OR D */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.d);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.d);
 
                 4
             }
             0xB3 => {
                 /* OR E */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.e);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.e);
 
                 4
             }
             0xB4 => {
                 /* OR H */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.h);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.h);
 
                 4
             }
             0xB5 => {
                 /* OR L */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.l);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.l);
 
                 4
             }
             0xB6 => {
                 /* OR (HL) */
                 let val = self.mmu.read(self.registers.get_hl());
-                self.registers.a = self.alu8_or(self.registers.a, val);
+                self.registers.a = self.registers.alu8_or(self.registers.a, val);
 
-                4
+                8
             }
             0xB7 => {
                 /* OR A */
-                self.registers.a = self.alu8_or(self.registers.a, self.registers.a);
+                self.registers.a = self.registers.alu8_or(self.registers.a, self.registers.a);
+
+                4
+            }
+            0xB8 => {
+                /* CP B */
+                self.registers.alu8_cp(self.registers.a, self.registers.b);
+
+                4
+            }
+            0xB9 => {
+                /* CP C */
+                self.registers.alu8_cp(self.registers.a, self.registers.c);
+
+                4
+            }
+            0xBA => {
+                /* CP D */
+                self.registers.alu8_cp(self.registers.a, self.registers.d);
+
+                4
+            }
+            0xBB => {
+                /* CP E */
+                self.registers.alu8_cp(self.registers.a, self.registers.e);
+
+                4
+            }
+            0xBC => {
+                /* CP H */
+                self.registers.alu8_cp(self.registers.a, self.registers.h);
+
+                4
+            }
+            0xBD => {
+                /* CP L */
+                self.registers.alu8_cp(self.registers.a, self.registers.l);
 
                 4
             }
             0xBE => {
                 /* CP (HL) */
-                self.alu8_cp(self.registers.a, self.mmu.read(self.registers.get_hl()));
+                let val = self.mmu.read(self.registers.get_hl());
+                self.registers.alu8_cp(self.registers.a, val);
 
                 8
             }
             0xBF => {
                 /* CP A */
-                let n = self.fetch_imm8();
-                self.registers.a = self.alu8_sub(self.registers.a, n);
+                self.registers.alu8_cp(self.registers.a, self.registers.a);
 
                 4
             }
+            0xC0 => {
+                /* RET NZ */
+                if !self.registers.get_flag(CpuFlag::Z) {
+                    self.registers.pc = self.pop_word();
+                    20
+                } else {
+                    8
+                }
+            }
             0xC1 => {
                 /* POP BC */
-                let addr = self.pop_word();
-                self.registers.set_bc(addr);
+                let bc = self.pop_word();
+                self.registers.set_bc(bc);
 
-                16
+                12
+            }
+            0xC2 => {
+                /* JP NZ,a16 */
+                let addr = self.fetch_imm16();
+
+                if !self.registers.get_flag(CpuFlag::Z) {
+                    self.registers.pc = addr;
+                    16
+                } else {
+                    12
+                }
             }
             0xC3 => {
                 /* JP a16 */
@@ -564,41 +1532,212 @@ impl CPU {
 
                 16
             }
+            0xC4 => {
+                /* CALL NZ,a16 */
+                let addr = self.fetch_imm16();
+
+                if !self.registers.get_flag(CpuFlag::Z) {
+                    self.push_word(self.registers.pc);
+                    self.registers.pc = addr;
+                    24
+                } else {
+                    12
+                }
+            }
             0xC5 => {
                 /* PUSH BC */
                 self.push_word(self.registers.get_bc());
 
                 16
             }
+            0xC6 => {
+                /* ADD A,d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.a = self.registers.alu8_add(self.registers.a, d8);
+
+                8
+            }
+            0xC7 => {
+                /* RST 00H */
+                self.rst(0x00);
+
+                16
+            }
             0xC8 => {
                 /* RET Z */
+                if self.registers.get_flag(CpuFlag::Z) {
+                    self.registers.pc = self.pop_word();
+                    20
+                } else {
+                    8
+                }
             }
             0xC9 => {
                 /* RET */
                 self.registers.pc = self.pop_word();
 
-                8
+                16
+            }
+            0xCA => {
+                /* JP Z,a16 */
+                let addr = self.fetch_imm16();
+
+                if self.registers.get_flag(CpuFlag::Z) {
+                    self.registers.pc = addr;
+                    16
+                } else {
+                    12
+                }
             }
             0xCB => {
                 /* Prefix CB */
                 let instr = self.fetch_imm8();
                 self.execute_cb_instr(instr)
             }
+            0xCC => {
+                /* CALL Z,a16 */
+                let addr = self.fetch_imm16();
+
+                if self.registers.get_flag(CpuFlag::Z) {
+                    self.push_word(self.registers.pc);
+                    self.registers.pc = addr;
+                    24
+                } else {
+                    12
+                }
+            }
             0xCD => {
-                /* CALL addr */
+                /* CALL a16 */
                 let addr = self.fetch_imm16();
                 self.push_word(self.registers.pc);
 
                 self.registers.pc = addr;
 
                 24
-            },
+            }
+            0xCE => {
+                /* ADC A,d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.a = self.registers.alu8_adc(self.registers.a, d8);
+
+                8
+            }
+            0xCF => {
+                /* RST 08H */
+                self.rst(0x08);
+
+                16
+            }
+            0xD0 => {
+                /* RET NC */
+                if !self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = self.pop_word();
+                    20
+                } else {
+                    8
+                }
+            }
+            0xD1 => {
+                /* POP DE */
+                let de = self.pop_word();
+                self.registers.set_de(de);
+
+                12
+            }
+            0xD2 => {
+                /* JP NC,a16 */
+                let addr = self.fetch_imm16();
+
+                if !self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = addr;
+                    16
+                } else {
+                    12
+                }
+            }
+            0xD4 => {
+                /* CALL NC,a16 */
+                let addr = self.fetch_imm16();
+
+                if !self.registers.get_flag(CpuFlag::C) {
+                    self.push_word(self.registers.pc);
+                    self.registers.pc = addr;
+                    24
+                } else {
+                    12
+                }
+            }
             0xD5 => {
                 /* PUSH DE */
                 self.push_word(self.registers.get_de());
 
                 16
             }
+            0xD6 => {
+                /* SUB d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.a = self.registers.alu8_sub(self.registers.a, d8);
+
+                8
+            }
+            0xD7 => {
+                /* RST 10H */
+                self.rst(0x10);
+
+                16
+            }
+            0xD8 => {
+                /* RET C */
+                if self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = self.pop_word();
+                    20
+                } else {
+                    8
+                }
+            }
+            0xD9 => {
+                /* RETI */
+                self.registers.pc = self.pop_word();
+                self.ime = true;
+
+                16
+            }
+            0xDA => {
+                /* JP C,a16 */
+                let addr = self.fetch_imm16();
+
+                if self.registers.get_flag(CpuFlag::C) {
+                    self.registers.pc = addr;
+                    16
+                } else {
+                    12
+                }
+            }
+            0xDC => {
+                /* CALL C,a16 */
+                let addr = self.fetch_imm16();
+
+                if self.registers.get_flag(CpuFlag::C) {
+                    self.push_word(self.registers.pc);
+                    self.registers.pc = addr;
+                    24
+                } else {
+                    12
+                }
+            }
+            0xDE => {
+                /* SBC A,d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.a = self.registers.alu8_sub_with_borrow(self.registers.a, d8);
+
+                8
+            }
+            0xDF => {
+                /* RST 18H */
+                self.rst(0x18);
+
+                16
+            }
             0xE0 => {
                 /* LDH (a8),A */
                 let addr = 0xFF00 + self.fetch_imm8() as u16;
@@ -614,22 +1753,40 @@ impl CPU {
                 12
             }
             0xE2 => {
-                /* LD (C), A */
+                /* LD (C),A */
                 self.mmu.write(0xFF00 + self.registers.c as u16, self.registers.a);
 
                 8
             }
+            0xE5 => {
+                /* PUSH HL */
+                self.push_word(self.registers.get_hl());
+
+                16
+            }
             0xE6 => {
                 /* AND d8 */
                 let d8 = self.fetch_imm8();
-                self.alu8_and(self.registers.a, d8);
+                self.registers.a = self.registers.alu8_and(self.registers.a, d8);
 
                 8
             }
+            0xE7 => {
+                /* RST 20H */
+                self.rst(0x20);
+
+                16
+            }
+            0xE8 => {
+                /* ADD SP,r8 */
+                let r8 = self.fetch_imm8() as i8;
+                self.registers.sp = self.registers.alu16_add_sp(self.registers.sp, r8);
+
+                16
+            }
             0xE9 => {
                 /* JP (HL) */
-                let hl = self.registers.get_hl();
-                self.registers.pc = self.mmu.read_wide(hl);
+                self.registers.pc = self.registers.get_hl();
 
                 4
             }
@@ -640,8 +1797,15 @@ impl CPU {
 
                 16
             }
+            0xEE => {
+                /* XOR d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.a = self.registers.alu8_xor(self.registers.a, d8);
+
+                8
+            }
             0xEF => {
-                /* RST 28h */
+                /* RST 28H */
                 self.rst(0x28);
 
                 16
@@ -653,104 +1817,182 @@ impl CPU {
 
                 12
             }
+            0xF1 => {
+                /* POP AF */
+                let af = self.pop_word();
+                self.registers.set_af(af);
+
+                12
+            }
+            0xF2 => {
+                /* LD A,(C) */
+                self.registers.a = self.mmu.read(0xFF00 + self.registers.c as u16);
+
+                8
+            }
             0xF3 => {
                 /* DI */
-                /* TODO: the IME needs to be set after the next instruction, this is not what is
-                 * going on so far, this is putting the timings off
-                 */
+                /* Unlike EI, DI takes effect immediately and cancels a pending EI. */
                 self.ime = false;
+                self.ime_scheduled = false;
 
                 4
             }
-            0xFB => {
-                /* EI */
-                /* TODO: the IME needs to be set after the next instruction, this is not what is
-                 * going on so far, this is putting the timings off
-                 */
-                self.ime = true;
+            0xF5 => {
+                /* PUSH AF */
+                self.push_word(self.registers.get_af());
 
-                4
+                16
             }
-            0xFE => {
+            0xF6 => {
+                /* OR d8 */
                 let d8 = self.fetch_imm8();
-                self.alu8_cp(self.registers.a, d8);
+                self.registers.a = self.registers.alu8_or(self.registers.a, d8);
 
                 8
             }
-            _ => {
-                // self.mmu.print_vram();
-                self.mmu.do_cycle();
-                self.debug_dump();
-                panic!(
-                    "Unimplemented instructions (opcode = {:2X}) at pc = {:4X}",
-                    opcode, self.registers.pc
-                );
+            0xF7 => {
+                /* RST 30H */
+                self.rst(0x30);
+
+                16
             }
-        }
-    }
+            0xF8 => {
+                /* LD HL,SP+r8 */
+                let r8 = self.fetch_imm8() as i8;
+                let res = self.registers.alu16_add_sp(self.registers.sp, r8);
+                self.registers.set_hl(res);
 
-    fn execute_cb_instr(&mut self, instr: u8) -> u8 {
-        match instr {
-            0x11 => {
-                /* RL C */
-                self.registers.c = self.alu8_rl(self.registers.c);
+                12
+            }
+            0xF9 => {
+                /* LD SP,HL */
+                self.registers.sp = self.registers.get_hl();
 
                 8
             }
-            0x37 => {
-                let low_nibble = self.registers.a & 0x0F;
-                self.registers.a >>= 4;
-                self.registers.a |= low_nibble << 4; 
+            0xFA => {
+                /* LD A,(a16) */
+                let addr = self.fetch_imm16();
+                self.registers.a = self.mmu.read(addr);
 
-                self.registers.set_flag(CpuFlag::Z, self.registers.a == 0);
-                self.registers.set_flag(CpuFlag::N, false);
-                self.registers.set_flag(CpuFlag::H, false);
-                self.registers.set_flag(CpuFlag::C, false);
+                16
+            }
+            0xFB => {
+                /* EI */
+                /* IME gets set once the next instruction has been executed. */
+                self.ime_scheduled = true;
 
-                8
+                4
             }
-            0x47 => {
-                /* BIT 0,A */
-                self.test_bit(self.registers.a, 0);
+            0xFE => {
+                /* CP d8 */
+                let d8 = self.fetch_imm8();
+                self.registers.alu8_cp(self.registers.a, d8);
 
                 8
             }
-            0x5F => {
-                /* BIT 3,A */
-                self.test_bit(self.registers.a, 3);
+            0xFF => {
+                /* RST 38H */
+                self.rst(0x38);
 
-                8
+                16
             }
-            0x7C => {
-                /* Bit 7,H */
-                let h = self.registers.h;
 
-                self.registers.set_flag(CpuFlag::Z, (h & (1 << 7)) > 0);
-                self.registers.set_flag(CpuFlag::N, false);
-                self.registers.set_flag(CpuFlag::H, true);
+            _ => {
+                /* The 11 remaining opcodes lock up the CPU on real hardware, it doesn't even
+                 * service interrupts anymore.
+                 */
+                self.locked = true;
 
-                8
+                4
             }
-            0xC8 => {
-                /* SET 1,B */
-                self.registers.b = CPU::set_bit(self.registers.b, 1);
+        }
+    }
 
-                8
+    fn execute_cb_instr(&mut self, instr: u8) -> u8 {
+        /* CB opcodes are laid out as 0bXXYYYZZZ:
+         *  - XX selects the operation group (rotate/shift, BIT, RES, SET),
+         *  - YYY is either the rotate/shift operation or the bit number,
+         *  - ZZZ is the operand (B, C, D, E, H, L, (HL), A).
+         */
+        let operand = instr & 0b111;
+        let n = (instr >> 3) & 0b111;
+        let val = self.read_operand(operand);
+
+        match instr >> 6 {
+            0b00 => {
+                let res = match n {
+                    0 => self.registers.alu8_rlc(val),
+                    1 => self.registers.alu8_rrc(val),
+                    2 => self.registers.alu8_rl(val),
+                    3 => self.registers.alu8_rr(val),
+                    4 => self.registers.alu8_sla(val),
+                    5 => self.registers.alu8_sra(val),
+                    6 => self.registers.alu8_swap(val),
+                    _ => self.registers.alu8_srl(val),
+                };
+                self.write_operand(operand, res);
+            }
+            0b01 => {
+                /* BIT n,r */
+                self.test_bit(val, n);
+
+                /* BIT n,(HL) only reads memory, so it is faster than the other (HL) variants. */
+                return if operand == 6 { 12 } else { 8 };
+            }
+            0b10 => {
+                /* RES n,r */
+                self.write_operand(operand, CPU::reset_bit(val, n));
+            }
+            _ => {
+                /* SET n,r */
+                self.write_operand(operand, CPU::set_bit(val, n));
             }
-            _ => panic!("Unimplemented CB instruction {:2X}", instr),
+        }
+
+        if operand == 6 { 16 } else { 8 }
+    }
+
+    /* Operand encoding shared by the CB prefixed instructions. */
+    fn read_operand(&self, operand: u8) -> u8 {
+        match operand {
+            0 => self.registers.b,
+            1 => self.registers.c,
+            2 => self.registers.d,
+            3 => self.registers.e,
+            4 => self.registers.h,
+            5 => self.registers.l,
+            6 => self.mmu.read(self.registers.get_hl()),
+            7 => self.registers.a,
+            _ => panic!("Invalid operand encoding {}", operand),
+        }
+    }
+
+    fn write_operand(&mut self, operand: u8, val: u8) {
+        match operand {
+            0 => self.registers.b = val,
+            1 => self.registers.c = val,
+            2 => self.registers.d = val,
+            3 => self.registers.e = val,
+            4 => self.registers.h = val,
+            5 => self.registers.l = val,
+            6 => self.mmu.write(self.registers.get_hl(), val),
+            7 => self.registers.a = val,
+            _ => panic!("Invalid operand encoding {}", operand),
         }
     }
 
     fn fetch_imm8(&mut self) -> u8 {
         let byte = self.mmu.read(self.registers.pc);
-        self.registers.pc += 1;
+        self.registers.pc = self.registers.pc.wrapping_add(1);
 
         byte
     }
 
     fn fetch_imm16(&mut self) -> u16 {
         let word = self.mmu.read_wide(self.registers.pc);
-        self.registers.pc += 2;
+        self.registers.pc = self.registers.pc.wrapping_add(2);
 
         word
     }
@@ -760,184 +2002,34 @@ impl CPU {
     }
 
     fn push_word(&mut self, val: u16) {
-        self.registers.sp -= 2;
+        self.registers.sp = self.registers.sp.wrapping_sub(2);
         self.mmu.write_wide(self.registers.sp, val);
     }
 
     fn pop_word(&mut self) -> u16 {
         let val = self.mmu.read_wide(self.registers.sp);
-        self.registers.sp += 2;
+        self.registers.sp = self.registers.sp.wrapping_add(2);
 
         val
     }
 
-    fn alu16_add(&mut self, a: u16, b: u16) -> u16 {
-        let (res, overflow) = a.overflowing_add(b);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, res & (1 << 4) > 0);
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_add(&mut self, a: u8, b: u8) -> u8 {
-        let (res, overflow) = a.overflowing_add(b);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_adc(&mut self, a: u8, b: u8) -> u8 {
-        let carry = match self.registers.get_flag(CpuFlag::C) {
-            true => 1,
-            false => 0,
-        };
-
-        let (res, overflow) = a.overflowing_add(b + carry);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_sub(&mut self, a: u8, b: u8) -> u8 {
-        let (res, overflow) = a.overflowing_sub(b);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, true);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_sub_with_borrow(&mut self, a: u8, b: u8) -> u8 {
-        let carry = match self.registers.get_flag(CpuFlag::C) {
-            true => 1,
-            false => 0,
-        };
-
-        let (res, overflow) = a.overflowing_sub(b + carry);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, true);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_sdc(&mut self, a: u8, b: u8) -> u8 {
-        let carry = match self.registers.get_flag(CpuFlag::C) {
-            true => 1,
-            false => 0,
-        };
-
-        let (res, overflow) = a.overflowing_sub(b + carry);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, true);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-
-        res
-    }
-
-    fn alu8_and(&mut self, a: u8, b: u8) -> u8 {
-        let res = a & b;
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, true);
-        self.registers.set_flag(CpuFlag::C, false);
-
-        res
-    }
-
-    fn alu8_or(&mut self, a: u8, b: u8) -> u8 {
-        let res = a | b;
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, false);
-        self.registers.set_flag(CpuFlag::C, false);
-
-        res
-    }
-
-    fn alu8_xor(&mut self, a: u8, b: u8) -> u8 {
-        let res = a ^ b;
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, false);
-        self.registers.set_flag(CpuFlag::C, false);
-
-        res
-    }
-
-    fn alu8_cp(&mut self, a: u8, b: u8) {
-        let (res, overflow) = a.overflowing_sub(b);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, true);
-        self.registers.set_flag(CpuFlag::H, (res & (1 << 4)) > 0); /* TODO: test H flag, it might be very broken */
-        self.registers.set_flag(CpuFlag::C, overflow);
-    }
-
-    fn alu8_inc(&mut self, n: u8) -> u8 {
-        let res = n.wrapping_add(1);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, (n & 0x0F) == 0);
-
-        res
-    }
-    fn alu8_dec(&mut self, n: u8) -> u8 {
-        let res = n.wrapping_sub(1);
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, true);
-        self.registers.set_flag(CpuFlag::H, (n & 0x0F) == 0);
-
-        res
-    }
-
-    fn alu8_rl(&mut self, n: u8) -> u8 {
-        let old_carry = if self.registers.get_flag(CpuFlag::C) { 1 } else { 0 };
-        let new_carry = n & 0x80 == 0x80;
-        let res = ((n << 1) & !0b1) | old_carry;
-
-        self.registers.set_flag(CpuFlag::Z, res == 0);
-        self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, false);
-        self.registers.set_flag(CpuFlag::C, new_carry);
-
-        res
-    }
-
     fn rst(&mut self, n: u8) {
         self.push_word(self.registers.pc);
         self.registers.pc = n as u16;
     }
 
     fn test_bit(&mut self, byte: u8, n: u8) {
-        self.registers.set_flag(CpuFlag::Z,  byte & (1 << n) > 0);
+        /* Z is set when the tested bit is 0. */
+        self.registers.set_flag(CpuFlag::Z, byte & (1 << n) == 0);
         self.registers.set_flag(CpuFlag::N, false);
-        self.registers.set_flag(CpuFlag::H, false);
+        self.registers.set_flag(CpuFlag::H, true);
     }
 
     fn set_bit(byte: u8, n: u8) -> u8 {
         byte | (1 << n)
     }
+
+    fn reset_bit(byte: u8, n: u8) -> u8 {
+        byte & !(1 << n)
+    }
 }
@@ -0,0 +1,61 @@
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/* RGBA values of the 4 DMG shades, from the lightest to the darkest. */
+const SHADES_RGBA: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF], /* White */
+    [0xD3, 0xD3, 0xD3, 0xFF], /* Light gray */
+    [0xA9, 0xA9, 0xA9, 0xFF], /* Dark gray */
+    [0x00, 0x00, 0x00, 0xFF], /* Black */
+];
+
+/* One frame as rendered by the PPU, stored as shade indices (0 = white, 3 = black). */
+pub struct Framebuffer {
+    shades: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            shades: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, shade: u8) {
+        self.shades[x + y * SCREEN_WIDTH] = shade;
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
+        self.shades[x + y * SCREEN_WIDTH]
+    }
+
+    /* Shade indices, row by row. */
+    pub fn shades(&self) -> &[u8] {
+        &self.shades
+    }
+
+    /* 4 bytes per pixel, row by row. */
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.shades
+            .iter()
+            .flat_map(|&shade| SHADES_RGBA[shade as usize].iter().cloned())
+            .collect()
+    }
+
+    /* One 0x00RRGGBB word per pixel, as most window libraries expect. */
+    pub fn to_rgb32(&self) -> Vec<u32> {
+        self.shades
+            .iter()
+            .map(|&shade| {
+                let [r, g, b, _] = SHADES_RGBA[shade as usize];
+                (r as u32) << 16 | (g as u32) << 8 | b as u32
+            })
+            .collect()
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
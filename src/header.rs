@@ -0,0 +1,489 @@
+use std::error;
+use std::fmt;
+
+/* Every game of a multicart has its own copy of the Nintendo logo. */
+pub const LOGO_START: usize = 0x104;
+pub const LOGO_SIZE: usize = 48;
+const TITLE_START: usize = 0x134;
+const MANUFACTURER_CODE_START: usize = 0x13F;
+const CGB_FLAG: usize = 0x143;
+const NEW_LICENSEE_START: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const DESTINATION: usize = 0x14A;
+const OLD_LICENSEE: usize = 0x14B;
+const VERSION: usize = 0x14C;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+
+pub const HEADER_END: usize = 0x150;
+
+/* What the biggest ROM size code (0x08) declares. */
+pub const MAX_ROM_SIZE: usize = 0x80_0000;
+
+/* MMM01 multicarts boot on a menu stored in their last 32KB. */
+const MMM01_MENU_SIZE: usize = 0x8000;
+
+/* Old licensee code telling the new 2 characters code should be used instead. */
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CartridgeError {
+    /* The ROM is shorter than its header, or than the ROM size the header declares. */
+    Truncated { len: usize, expected: usize },
+    UnsupportedType(CartridgeType),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Truncated { len, expected } => {
+                write!(f, "truncated ROM, {} bytes long instead of {}", len, expected)
+            }
+            CartridgeError::UnsupportedType(cartridge_type) => {
+                write!(f, "unsupported cartridge type {}", cartridge_type)
+            }
+        }
+    }
+}
+
+impl error::Error for CartridgeError {}
+
+/* Byte 0x147 of the header, telling which hardware the cartridge has. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CartridgeType {
+    RomOnly,
+    MBC1,
+    MBC1Ram,
+    MBC1RamBattery,
+    MBC2,
+    MBC2Battery,
+    RomRam,
+    RomRamBattery,
+    MMM01,
+    MMM01Ram,
+    MMM01RamBattery,
+    MBC3TimerBattery,
+    MBC3TimerRamBattery,
+    MBC3,
+    MBC3Ram,
+    MBC3RamBattery,
+    MBC5,
+    MBC5Ram,
+    MBC5RamBattery,
+    MBC5Rumble,
+    MBC5RumbleRam,
+    MBC5RumbleRamBattery,
+    MBC6,
+    MBC7SensorRumbleRamBattery,
+    PocketCamera,
+    BandaiTama5,
+    HuC3,
+    HuC1RamBattery,
+    Unknown(u8),
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> CartridgeType {
+        match code {
+            0x00 => CartridgeType::RomOnly,
+            0x01 => CartridgeType::MBC1,
+            0x02 => CartridgeType::MBC1Ram,
+            0x03 => CartridgeType::MBC1RamBattery,
+            0x05 => CartridgeType::MBC2,
+            0x06 => CartridgeType::MBC2Battery,
+            0x08 => CartridgeType::RomRam,
+            0x09 => CartridgeType::RomRamBattery,
+            0x0B => CartridgeType::MMM01,
+            0x0C => CartridgeType::MMM01Ram,
+            0x0D => CartridgeType::MMM01RamBattery,
+            0x0F => CartridgeType::MBC3TimerBattery,
+            0x10 => CartridgeType::MBC3TimerRamBattery,
+            0x11 => CartridgeType::MBC3,
+            0x12 => CartridgeType::MBC3Ram,
+            0x13 => CartridgeType::MBC3RamBattery,
+            0x19 => CartridgeType::MBC5,
+            0x1A => CartridgeType::MBC5Ram,
+            0x1B => CartridgeType::MBC5RamBattery,
+            0x1C => CartridgeType::MBC5Rumble,
+            0x1D => CartridgeType::MBC5RumbleRam,
+            0x1E => CartridgeType::MBC5RumbleRamBattery,
+            0x20 => CartridgeType::MBC6,
+            0x22 => CartridgeType::MBC7SensorRumbleRamBattery,
+            0xFC => CartridgeType::PocketCamera,
+            0xFD => CartridgeType::BandaiTama5,
+            0xFE => CartridgeType::HuC3,
+            0xFF => CartridgeType::HuC1RamBattery,
+            code => CartridgeType::Unknown(code),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            CartridgeType::RomOnly => 0x00,
+            CartridgeType::MBC1 => 0x01,
+            CartridgeType::MBC1Ram => 0x02,
+            CartridgeType::MBC1RamBattery => 0x03,
+            CartridgeType::MBC2 => 0x05,
+            CartridgeType::MBC2Battery => 0x06,
+            CartridgeType::RomRam => 0x08,
+            CartridgeType::RomRamBattery => 0x09,
+            CartridgeType::MMM01 => 0x0B,
+            CartridgeType::MMM01Ram => 0x0C,
+            CartridgeType::MMM01RamBattery => 0x0D,
+            CartridgeType::MBC3TimerBattery => 0x0F,
+            CartridgeType::MBC3TimerRamBattery => 0x10,
+            CartridgeType::MBC3 => 0x11,
+            CartridgeType::MBC3Ram => 0x12,
+            CartridgeType::MBC3RamBattery => 0x13,
+            CartridgeType::MBC5 => 0x19,
+            CartridgeType::MBC5Ram => 0x1A,
+            CartridgeType::MBC5RamBattery => 0x1B,
+            CartridgeType::MBC5Rumble => 0x1C,
+            CartridgeType::MBC5RumbleRam => 0x1D,
+            CartridgeType::MBC5RumbleRamBattery => 0x1E,
+            CartridgeType::MBC6 => 0x20,
+            CartridgeType::MBC7SensorRumbleRamBattery => 0x22,
+            CartridgeType::PocketCamera => 0xFC,
+            CartridgeType::BandaiTama5 => 0xFD,
+            CartridgeType::HuC3 => 0xFE,
+            CartridgeType::HuC1RamBattery => 0xFF,
+            CartridgeType::Unknown(code) => code,
+        }
+    }
+
+    /* HuC3 and the Pocket Camera keep their RAM (and clock) alive with a battery even though the
+     * name doesn't say so.
+     */
+    pub fn has_battery(self) -> bool {
+        matches!(
+            self,
+            CartridgeType::MBC1RamBattery
+                | CartridgeType::MBC2Battery
+                | CartridgeType::RomRamBattery
+                | CartridgeType::MMM01RamBattery
+                | CartridgeType::MBC3TimerBattery
+                | CartridgeType::MBC3TimerRamBattery
+                | CartridgeType::MBC3RamBattery
+                | CartridgeType::MBC5RamBattery
+                | CartridgeType::MBC5RumbleRamBattery
+                | CartridgeType::MBC7SensorRumbleRamBattery
+                | CartridgeType::PocketCamera
+                | CartridgeType::HuC3
+                | CartridgeType::HuC1RamBattery
+        )
+    }
+
+    pub fn has_rtc(self) -> bool {
+        matches!(
+            self,
+            CartridgeType::MBC3TimerBattery | CartridgeType::MBC3TimerRamBattery | CartridgeType::HuC3
+        )
+    }
+
+    pub fn has_rumble(self) -> bool {
+        matches!(
+            self,
+            CartridgeType::MBC5Rumble
+                | CartridgeType::MBC5RumbleRam
+                | CartridgeType::MBC5RumbleRamBattery
+                | CartridgeType::MBC7SensorRumbleRamBattery
+        )
+    }
+}
+
+impl fmt::Display for CartridgeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CartridgeType::RomOnly => "ROM ONLY",
+            CartridgeType::MBC1 => "MBC1",
+            CartridgeType::MBC1Ram => "MBC1+RAM",
+            CartridgeType::MBC1RamBattery => "MBC1+RAM+BATTERY",
+            CartridgeType::MBC2 => "MBC2",
+            CartridgeType::MBC2Battery => "MBC2+BATTERY",
+            CartridgeType::RomRam => "ROM+RAM",
+            CartridgeType::RomRamBattery => "ROM+RAM+BATTERY",
+            CartridgeType::MMM01 => "MMM01",
+            CartridgeType::MMM01Ram => "MMM01+RAM",
+            CartridgeType::MMM01RamBattery => "MMM01+RAM+BATTERY",
+            CartridgeType::MBC3TimerBattery => "MBC3+TIMER+BATTERY",
+            CartridgeType::MBC3TimerRamBattery => "MBC3+TIMER+RAM+BATTERY",
+            CartridgeType::MBC3 => "MBC3",
+            CartridgeType::MBC3Ram => "MBC3+RAM",
+            CartridgeType::MBC3RamBattery => "MBC3+RAM+BATTERY",
+            CartridgeType::MBC5 => "MBC5",
+            CartridgeType::MBC5Ram => "MBC5+RAM",
+            CartridgeType::MBC5RamBattery => "MBC5+RAM+BATTERY",
+            CartridgeType::MBC5Rumble => "MBC5+RUMBLE",
+            CartridgeType::MBC5RumbleRam => "MBC5+RUMBLE+RAM",
+            CartridgeType::MBC5RumbleRamBattery => "MBC5+RUMBLE+RAM+BATTERY",
+            CartridgeType::MBC6 => "MBC6",
+            CartridgeType::MBC7SensorRumbleRamBattery => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            CartridgeType::PocketCamera => "POCKET CAMERA",
+            CartridgeType::BandaiTama5 => "BANDAI TAMA5",
+            CartridgeType::HuC3 => "HuC3",
+            CartridgeType::HuC1RamBattery => "HuC1+RAM+BATTERY",
+            CartridgeType::Unknown(_) => "UNKNOWN",
+        };
+
+        write!(f, "{} ({:02X})", name, self.code())
+    }
+}
+
+/* Byte 0x143 of the header. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CgbSupport {
+    DmgOnly,
+    /* Works on both the DMG and the CGB. */
+    Enhanced,
+    CgbOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/* The cartridge header, at 0x100-0x14F of the ROM. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    /* Only on newer cartridges, where it takes the end of the title area. */
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub old_licensee: u8,
+    pub new_licensee: String,
+    pub cartridge_type: CartridgeType,
+    /* In bytes, the ROM length is used when the header size code is unknown. */
+    pub rom_size: usize,
+    /* External RAM in bytes, 0 for MBC2 which has its own built-in RAM. */
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+/* Where the header the console boots on is. That's the first one, except on MMM01 multicarts which
+ * store the menu, with a header describing the whole cartridge, after the games.
+ */
+fn header_offset(rom: &[u8]) -> usize {
+    let menu = match rom.len().checked_sub(MMM01_MENU_SIZE) {
+        Some(menu) if menu > 0 => menu,
+        _ => return 0,
+    };
+
+    let is_mmm01 = matches!(rom[menu + CARTRIDGE_TYPE], 0x0B..=0x0D);
+    let has_logo = rom[menu + LOGO_START..menu + LOGO_START + LOGO_SIZE] == rom[LOGO_START..LOGO_START + LOGO_SIZE];
+
+    match is_mmm01 && has_logo {
+        true => menu,
+        false => 0,
+    }
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated { len: rom.len(), expected: HEADER_END });
+        }
+
+        let offset = header_offset(rom);
+        let header = &rom[offset..];
+
+        let cgb_support = match header[CGB_FLAG] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::CgbOnly,
+            _ => CgbSupport::DmgOnly,
+        };
+
+        /* CGB era cartridges use the last 5 bytes of the title for the CGB flag and the
+         * manufacturer code.
+         */
+        let (title, manufacturer_code) = match cgb_support {
+            CgbSupport::DmgOnly => (ascii(&header[TITLE_START..CGB_FLAG + 1]), None),
+            _ => {
+                let manufacturer_code = ascii(&header[MANUFACTURER_CODE_START..CGB_FLAG]);
+
+                (
+                    ascii(&header[TITLE_START..MANUFACTURER_CODE_START]),
+                    Some(manufacturer_code).filter(|code| code.len() == 4),
+                )
+            }
+        };
+
+        let rom_size = match header[ROM_SIZE] {
+            n @ 0x00..=0x08 => 0x8000 << n,
+            0x52 => 72 * 0x4000,
+            0x53 => 80 * 0x4000,
+            0x54 => 96 * 0x4000,
+            _ => rom.len(),
+        };
+
+        if rom.len() < rom_size {
+            return Err(CartridgeError::Truncated { len: rom.len(), expected: rom_size });
+        }
+
+        let cartridge_type = CartridgeType::from_code(header[CARTRIDGE_TYPE]);
+
+        let ram_size = match (cartridge_type, header[RAM_SIZE]) {
+            (CartridgeType::MBC2, _) | (CartridgeType::MBC2Battery, _) => 0,
+            (_, 0x01) => 0x800,
+            (_, 0x02) => 0x2000,
+            (_, 0x03) => 0x8000,
+            (_, 0x04) => 0x20000,
+            (_, 0x05) => 0x10000,
+            _ => 0,
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+            cgb_support,
+            sgb_support: header[SGB_FLAG] == 0x03,
+            old_licensee: header[OLD_LICENSEE],
+            new_licensee: ascii(&header[NEW_LICENSEE_START..NEW_LICENSEE_START + 2]),
+            cartridge_type,
+            rom_size,
+            ram_size,
+            destination: match header[DESTINATION] {
+                0x00 => Destination::Japan,
+                _ => Destination::Overseas,
+            },
+            version: header[VERSION],
+            header_checksum: header[HEADER_CHECKSUM],
+            global_checksum: (header[GLOBAL_CHECKSUM] as u16) << 8 | header[GLOBAL_CHECKSUM + 1] as u16,
+
+            computed_header_checksum: header[TITLE_START..HEADER_CHECKSUM]
+                .iter()
+                .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1)),
+            computed_global_checksum: rom
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != offset + GLOBAL_CHECKSUM && i != offset + GLOBAL_CHECKSUM + 1)
+                .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16)),
+        })
+    }
+
+    /* The boot ROM refuses to start the cartridge when this one is wrong. */
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    /* Never checked by the hardware, a mismatch usually means a corrupted or patched ROM. */
+    pub fn is_global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    /* The 2 characters new code when the old one tells to use it, the old one in hex otherwise. */
+    pub fn licensee(&self) -> String {
+        match self.old_licensee {
+            USE_NEW_LICENSEE => self.new_licensee.clone(),
+            code => format!("{:02X}", code),
+        }
+    }
+}
+
+/* Zero padded ASCII string. */
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom_with_header() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+
+        rom[TITLE_START..TITLE_START + 6].copy_from_slice(b"TETRIS");
+        rom[CARTRIDGE_TYPE] = 0x13;
+        rom[RAM_SIZE] = 0x03;
+        rom[DESTINATION] = 0x01;
+        rom[OLD_LICENSEE] = USE_NEW_LICENSEE;
+        rom[NEW_LICENSEE_START..NEW_LICENSEE_START + 2].copy_from_slice(b"01");
+        rom[VERSION] = 0x01;
+
+        rom
+    }
+
+    fn fix_checksums(rom: &mut [u8]) {
+        rom[HEADER_CHECKSUM] = CartridgeHeader::parse(rom).unwrap().computed_header_checksum;
+
+        let global = CartridgeHeader::parse(rom).unwrap().computed_global_checksum;
+        rom[GLOBAL_CHECKSUM] = (global >> 8) as u8;
+        rom[GLOBAL_CHECKSUM + 1] = global as u8;
+    }
+
+    #[test]
+    fn parse_fields() {
+        let header = CartridgeHeader::parse(&rom_with_header()).unwrap();
+
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.manufacturer_code, None);
+        assert_eq!(header.cgb_support, CgbSupport::DmgOnly);
+        assert_eq!(header.cartridge_type, CartridgeType::MBC3RamBattery);
+        assert!(header.cartridge_type.has_battery());
+        assert_eq!(header.rom_size, 0x8000);
+        assert_eq!(header.ram_size, 0x8000);
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.licensee(), "01");
+        assert_eq!(header.version, 0x01);
+    }
+
+    #[test]
+    fn cgb_title() {
+        let mut rom = rom_with_header();
+        rom[MANUFACTURER_CODE_START..CGB_FLAG].copy_from_slice(b"AXVE");
+        rom[CGB_FLAG] = 0x80;
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.cgb_support, CgbSupport::Enhanced);
+        assert_eq!(header.manufacturer_code.as_deref(), Some("AXVE"));
+    }
+
+    #[test]
+    fn checksums() {
+        let mut rom = rom_with_header();
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(!header.is_header_checksum_valid());
+
+        fix_checksums(&mut rom);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(header.is_header_checksum_valid());
+        assert!(header.is_global_checksum_valid());
+
+        rom[0x4000] ^= 0xFF;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(header.is_header_checksum_valid());
+        assert!(!header.is_global_checksum_valid());
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(
+            CartridgeHeader::parse(&[0; 0x100]),
+            Err(CartridgeError::Truncated { len: 0x100, expected: HEADER_END })
+        );
+
+        let mut rom = rom_with_header();
+        rom[ROM_SIZE] = 0x01;
+        assert_eq!(
+            CartridgeHeader::parse(&rom),
+            Err(CartridgeError::Truncated { len: 0x8000, expected: 0x10000 })
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/* Infrared LED and receiver found on some cartridges. Unless connected to another port the
+ * receiver never sees any light.
+ */
+pub struct Infrared {
+    led: Arc<AtomicBool>,
+    remote_led: Option<Arc<AtomicBool>>,
+}
+
+impl Infrared {
+    pub fn new() -> Infrared {
+        Infrared {
+            led: Arc::new(AtomicBool::new(false)),
+            remote_led: None,
+        }
+    }
+
+    /* Points both ports at each other, each one then sees the light of the other's LED. */
+    pub fn connect(&mut self, other: &mut Infrared) {
+        self.remote_led = Some(other.led.clone());
+        other.remote_led = Some(self.led.clone());
+    }
+
+    pub fn set_led(&mut self, on: bool) {
+        self.led.store(on, Ordering::Relaxed);
+    }
+
+    pub fn sees_light(&self) -> bool {
+        match &self.remote_led {
+            Some(remote_led) => remote_led.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    /* Value read from the cartridge IR register: 0xC1 when light is received, 0xC0 otherwise. */
+    pub fn read(&self) -> u8 {
+        0xC0 | self.sees_light() as u8
+    }
+
+    /* Bit 0 drives the LED. */
+    pub fn write(&mut self, val: u8) {
+        self.set_led(val & 0x01 != 0);
+    }
+}
+
+impl Default for Infrared {
+    fn default() -> Infrared {
+        Infrared::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_light_when_alone() {
+        let mut infrared = Infrared::new();
+
+        infrared.write(0x01);
+        assert_eq!(infrared.read(), 0xC0);
+    }
+
+    #[test]
+    fn looped_back() {
+        let mut a = Infrared::new();
+        let mut b = Infrared::new();
+        a.connect(&mut b);
+
+        a.write(0x01);
+        assert_eq!(b.read(), 0xC1);
+        assert_eq!(a.read(), 0xC0);
+
+        a.write(0x00);
+        b.write(0x01);
+        assert_eq!(b.read(), 0xC0);
+        assert_eq!(a.read(), 0xC1);
+    }
+}
@@ -0,0 +1,34 @@
+/* Bits of the IE (0xFFFF) and IF (0xFF0F) registers. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank  = 0b0_0001,
+    LCDStat = 0b0_0010,
+    Timer   = 0b0_0100,
+    Serial  = 0b0_1000,
+    Joypad  = 0b1_0000,
+}
+
+/* Ordered by priority, the lowest bit is serviced first. */
+pub const INTERRUPTS: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::LCDStat,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Joypad,
+];
+
+impl Interrupt {
+    pub fn mask(self) -> u8 {
+        self as u8
+    }
+
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::VBlank  => 0x40,
+            Interrupt::LCDStat => 0x48,
+            Interrupt::Timer   => 0x50,
+            Interrupt::Serial  => 0x58,
+            Interrupt::Joypad  => 0x60,
+        }
+    }
+}
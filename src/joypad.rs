@@ -1,5 +1,4 @@
-use crate::lcd;
-
+#[derive(Clone, Copy)]
 pub enum Keys {
     Down,
     Up,
@@ -11,59 +10,160 @@ pub enum Keys {
     A,
 }
 
+/* State of every button, true when pressed. */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Buttons {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+}
+
+impl Buttons {
+    pub fn is_pressed(&self, key: Keys) -> bool {
+        match key {
+            Keys::Down => self.down,
+            Keys::Up => self.up,
+            Keys::Left => self.left,
+            Keys::Right => self.right,
+            Keys::Start => self.start,
+            Keys::Select => self.select,
+            Keys::B => self.b,
+            Keys::A => self.a,
+        }
+    }
+}
+
+const SELECT_DIRECTION_KEYS: u8 = 1 << 4;
+const SELECT_BUTTON_KEYS: u8 = 1 << 5;
+
+/* P1 register. The select lines (bits 4-5) and the key lines (bits 0-3) are all active low:
+ * a key line reads 0 when its key is pressed and its group is selected.
+ */
 pub struct Joypad {
-    p1: u8,
+    select: u8,
+    buttons: Buttons,
 }
 
 impl Joypad {
     pub fn new()  -> Joypad {
         Joypad {
-            p1: 0,
+            select: SELECT_DIRECTION_KEYS | SELECT_BUTTON_KEYS,
+            buttons: Buttons::default(),
         }
     }
 
     pub fn read(&self) -> u8 {
-        self.p1
+        /* Bits 6-7 are unused and read as 1. */
+        0xC0 | self.select | self.key_lines()
     }
 
-    pub fn write(&mut self, p1: u8) {
-        self.p1 = p1 & 0xF8;
+    /* Only the select lines are writable. Returns true when a key line went from high to low,
+     * which raises the joypad interrupt.
+     */
+    pub fn write(&mut self, p1: u8) -> bool {
+        let before = self.key_lines();
+
+        self.select = p1 & (SELECT_DIRECTION_KEYS | SELECT_BUTTON_KEYS);
+
+        Joypad::has_falling_edge(before, self.key_lines())
+    }
+
+    /* Returns true when a key line went from high to low, see write. */
+    pub fn set_buttons(&mut self, buttons: Buttons) -> bool {
+        let before = self.key_lines();
+
+        self.buttons = buttons;
+
+        Joypad::has_falling_edge(before, self.key_lines())
+    }
+
+    fn has_falling_edge(before: u8, after: u8) -> bool {
+        before & !after != 0
     }
 
-    pub fn do_cycle(&mut self, input_lcd: &lcd::LCD) {
-        if self.is_selected_button_keys() {
-            self.set_key(Keys::A, input_lcd.get_key(Keys::A));
-            self.set_key(Keys::B, input_lcd.get_key(Keys::B));
-            self.set_key(Keys::Select, input_lcd.get_key(Keys::Select));
-            self.set_key(Keys::Start, input_lcd.get_key(Keys::Start));
+    fn key_lines(&self) -> u8 {
+        let mut lines = 0x0F;
+
+        if self.select & SELECT_BUTTON_KEYS == 0 {
+            lines &= self.group_lines([Keys::Start, Keys::Select, Keys::B, Keys::A]);
         }
 
-        if self.is_selected_direction_keys() {
-            self.set_key(Keys::Down, input_lcd.get_key(Keys::Down));
-            self.set_key(Keys::Up, input_lcd.get_key(Keys::Up));
-            self.set_key(Keys::Left, input_lcd.get_key(Keys::Left));
-            self.set_key(Keys::Right, input_lcd.get_key(Keys::Right));
+        if self.select & SELECT_DIRECTION_KEYS == 0 {
+            lines &= self.group_lines([Keys::Down, Keys::Up, Keys::Left, Keys::Right]);
         }
 
-        eprintln!("p1: {:8b}", self.p1);
+        lines
     }
 
-    fn is_selected_button_keys(&self) -> bool {
-        self.p1 & (1 << 5) == 0
+    /* Keys are given from bit 3 down to bit 0. */
+    fn group_lines(&self, keys: [Keys; 4]) -> u8 {
+        keys.iter()
+            .fold(0, |lines, &key| lines << 1 | !self.buttons.is_pressed(key) as u8)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nothing_selected_reads_all_ones() {
+        let mut joypad = Joypad::new();
+
+        joypad.set_buttons(Buttons { a: true, down: true, ..Buttons::default() });
+        joypad.write(0x30);
+
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn select_lines() {
+        let mut joypad = Joypad::new();
+
+        joypad.set_buttons(Buttons { a: true, start: true, left: true, ..Buttons::default() });
 
-    fn is_selected_direction_keys(&self) -> bool {
-        self.p1 & (1 << 4) == 0
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xE0 | 0x0D);
+
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xD0 | 0x06);
+
+        /* Both groups selected, lines are ANDed together. */
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC0 | 0x04);
+    }
+
+    #[test]
+    fn released_keys_read_as_one() {
+        let mut joypad = Joypad::new();
+
+        joypad.write(0x10);
+        joypad.set_buttons(Buttons { b: true, ..Buttons::default() });
+        assert_eq!(joypad.read() & 0x0F, 0x0D);
+
+        joypad.set_buttons(Buttons::default());
+        assert_eq!(joypad.read() & 0x0F, 0x0F);
     }
 
-    fn set_key(&mut self, key: Keys, pressed: bool) {
-        let bit = match key {
-            Keys::Down  | Keys::Start  => 3,
-            Keys::Up    | Keys::Select => 2,
-            Keys::Left  | Keys::B      => 1,
-            Keys::Right | Keys::A      => 0,
-        };
+    #[test]
+    fn interrupt_on_falling_edge() {
+        let mut joypad = Joypad::new();
+
+        /* Pressing a key of a group that isn't selected doesn't change the lines. */
+        assert!(!joypad.write(0x20));
+        assert!(!joypad.set_buttons(Buttons { a: true, ..Buttons::default() }));
+
+        assert!(joypad.set_buttons(Buttons { a: true, up: true, ..Buttons::default() }));
+        /* Releasing is a rising edge. */
+        assert!(!joypad.set_buttons(Buttons::default()));
 
-        self.p1 &= !(1 << bit);
+        /* Selecting a group while one of its keys is held also pulls a line low. */
+        joypad.set_buttons(Buttons { a: true, ..Buttons::default() });
+        assert!(joypad.write(0x10));
     }
 }
@@ -1,80 +1,74 @@
 extern crate minifb;
 
-use minifb::{Key, Window, WindowOptions};
-use crate::joypad;
-
-const LCD_HEIGHT: usize = 160;
-const LCD_WIDTH: usize = 144;
-
-/* Colors encoded using ARGB format */
-pub enum Colors {
-    Black     = 0x00_00_00_00,
-    White     = 0x00_FF_FF_FF,
-    LightGray = 0x00_D3_D3_D3,
-    DarkGray  = 0x00_A9_A9_A9,
-}
+use minifb::{Key, Scale, Window, WindowOptions};
+use gameboy_rs::{Buttons, Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
 
+/* minifb frontend, displaying the frames rendered by the PPU in a window. */
 pub struct LCD {
-    width: usize,
-    height: usize,
-    pixels: Vec<u32>,
     window: Window,
 }
 
 impl LCD {
-    pub fn new(width: usize, height: usize) -> LCD {
+    pub fn new(scale: usize) -> Result<LCD, minifb::Error> {
+        let scale = match scale {
+            1 => Scale::X1,
+            2 => Scale::X2,
+            4 => Scale::X4,
+            8 => Scale::X8,
+            16 => Scale::X16,
+            _ => Scale::X32,
+        };
+
         let mut lcd = LCD {
-            width,
-            height,
-            pixels: vec![0; width * height],
             window: Window::new(
                 "gameboy-rs",
-                width,
-                height,
-                WindowOptions::default(),
-            ).unwrap(),
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+                WindowOptions { scale, ..WindowOptions::default() },
+            )?,
         };
 
-        lcd.reset();
-        lcd.update();
+        lcd.draw(&Framebuffer::new());
 
-        lcd
+        Ok(lcd)
     }
 
-    pub fn reset(&mut self) {
-        for n in self.pixels.iter_mut() {
-            *n = Colors::White as u32;
-        }
+    pub fn draw(&mut self, framebuffer: &Framebuffer) {
+        self.window.update_with_buffer(&framebuffer.to_rgb32()).unwrap();
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, val: u32) {
-        self.pixels[x + y * self.width] = val
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
     }
 
-    pub fn update(&mut self) {
-        self.window.update_with_buffer(&self.pixels).unwrap();
-    }
+    /* Tilt for accelerometer cartridges, as (x, y) in g: IJKL tilts by half a g, with Shift by a
+     * full one.
+     */
+    pub fn get_tilt(&self) -> (f32, f32) {
+        let shift = self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+        let amount = match shift {
+            true => 1.0,
+            false => 0.5,
+        };
+        let axis = |negative, positive| match (self.window.is_key_down(negative), self.window.is_key_down(positive)) {
+            (true, false) => -amount,
+            (false, true) => amount,
+            _ => 0.0,
+        };
 
-    pub fn run_until_escape(&self) {
-        while self.window.is_open() {
-            if self.window.is_key_down(Key::Escape) {
-                break;
-            }
-        }
+        (axis(Key::J, Key::L), axis(Key::I, Key::K))
     }
 
-    pub fn get_key(&self, key: joypad::Keys) -> bool {
-        let minifb_key = match key {
-            joypad::Keys::A => Key::W,
-            joypad::Keys::B => Key::Q,
-            joypad::Keys::Select => Key::A,
-            joypad::Keys::Start => Key::S,
-            joypad::Keys::Left => Key::Left,
-            joypad::Keys::Right => Key::Right,
-            joypad::Keys::Up => Key::Up,
-            joypad::Keys::Down => Key::Down,
-        };
-
-        self.window.is_key_down(minifb_key)
+    pub fn get_buttons(&self) -> Buttons {
+        Buttons {
+            a: self.window.is_key_down(Key::W),
+            b: self.window.is_key_down(Key::Q),
+            select: self.window.is_key_down(Key::A),
+            start: self.window.is_key_down(Key::S),
+            right: self.window.is_key_down(Key::Right),
+            left: self.window.is_key_down(Key::Left),
+            up: self.window.is_key_down(Key::Up),
+            down: self.window.is_key_down(Key::Down),
+        }
     }
 }
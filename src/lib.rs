@@ -0,0 +1,414 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path;
+
+mod alu;
+mod archive;
+mod camera;
+mod cpu;
+mod decode;
+mod framebuffer;
+mod header;
+mod infrared;
+mod interrupt;
+mod joypad;
+mod mbc;
+mod mmu;
+mod palette;
+mod patch;
+mod ppu;
+mod registers;
+mod serial;
+mod timer;
+
+pub use crate::archive::{extract_rom, read_rom_file};
+pub use crate::camera::{ImageSource, StillImage, TestPattern, SENSOR_HEIGHT, SENSOR_WIDTH};
+pub use crate::framebuffer::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use crate::header::{CartridgeError, CartridgeHeader, CartridgeType, CgbSupport, Destination};
+pub use crate::joypad::Buttons;
+pub use crate::mbc::{detect_mapper, Mapper};
+pub use crate::patch::{apply_patch, find_patch, PatchError};
+pub use crate::mmu::DMG_ROM_SIZE as BOOT_ROM_SIZE;
+pub use crate::registers::{CpuFlag, Registers};
+
+/* Number of cycles it takes the PPU to draw a whole frame. */
+pub const CYCLES_PER_FRAME: usize = 70224;
+pub const CYCLES_PER_SECOND: usize = 4_194_304;
+
+/* What runs before the cartridge code. */
+pub enum BootRom {
+    /* The original DMG boot ROM, scrolling the Nintendo logo down. */
+    Dmg,
+    /* A user provided boot ROM, BOOT_ROM_SIZE bytes long. */
+    Custom(Vec<u8>),
+    /* Start at the cartridge entry point, as if the DMG boot ROM just finished. */
+    Skip,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameBoyError {
+    Cartridge(CartridgeError),
+    /* A custom boot ROM that isn't the size of the DMG one. */
+    BootRomSize { len: usize, expected: usize },
+}
+
+impl fmt::Display for GameBoyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameBoyError::Cartridge(err) => write!(f, "{}", err),
+            GameBoyError::BootRomSize { len, expected } => {
+                write!(f, "boot ROM is {} bytes long instead of {}", len, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for GameBoyError {}
+
+impl From<CartridgeError> for GameBoyError {
+    fn from(err: CartridgeError) -> GameBoyError {
+        GameBoyError::Cartridge(err)
+    }
+}
+
+/* The emulated console, built from a ROM image. */
+pub struct GameBoy {
+    cpu: cpu::CPU,
+    header: CartridgeHeader,
+    mapper: Mapper,
+}
+
+impl GameBoy {
+    pub fn new(rom: Vec<u8>) -> Result<GameBoy, GameBoyError> {
+        GameBoy::with_boot_rom(rom, BootRom::Dmg)
+    }
+
+    pub fn with_boot_rom(rom: Vec<u8>, boot_rom: BootRom) -> Result<GameBoy, GameBoyError> {
+        if let BootRom::Custom(ref boot_rom) = boot_rom {
+            if boot_rom.len() != BOOT_ROM_SIZE {
+                return Err(GameBoyError::BootRomSize { len: boot_rom.len(), expected: BOOT_ROM_SIZE });
+            }
+        }
+
+        let header = CartridgeHeader::parse(&rom)?;
+        let mapper = mbc::detect_mapper(&header, &rom)?;
+        let mut cpu = cpu::CPU::new(mbc::load_cartridge(&header, mapper, rom));
+
+        match boot_rom {
+            BootRom::Dmg => {},
+            BootRom::Custom(boot_rom) => cpu.set_boot_rom(boot_rom),
+            BootRom::Skip => cpu.skip_boot_rom(),
+        }
+
+        Ok(GameBoy { cpu, header, mapper })
+    }
+
+    /* The ROM can be compressed, see read_rom_file. Also applies the patch (see find_patch) and
+     * loads the battery save sitting next to it, if there are some.
+     */
+    pub fn from_file(rom_path: &path::Path) -> io::Result<GameBoy> {
+        let mut rom = read_rom_file(rom_path, None)?;
+
+        if let Some(patch_path) = find_patch(rom_path) {
+            rom = apply_patch(&rom, &fs::read(patch_path)?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+
+        let mut gameboy = GameBoy::new(rom).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        gameboy.load_save(&save_path(rom_path, None))?;
+
+        Ok(gameboy)
+    }
+
+    /* Runs until the PPU finished a frame. When the LCD is off no frame ever gets drawn, so this
+     * also stops once a frame worth of cycles went by.
+     */
+    pub fn run_frame(&mut self) {
+        let mut cycles = 0;
+
+        while cycles < CYCLES_PER_FRAME {
+            cycles += self.cpu.step_instruction();
+
+            /* Nothing happens in STOP mode until a button gets pressed. */
+            if self.cpu.take_frame_ready() || self.cpu.is_stopped() {
+                break;
+            }
+        }
+    }
+
+    /* Executes a single instruction, returns the number of cycles it took. */
+    pub fn step_instruction(&mut self) -> usize {
+        self.cpu.step_instruction()
+    }
+
+    /* Whether the CPU ran into one of the illegal opcodes. It then stays locked up until the
+     * emulator is reset, while the rest of the hardware keeps running.
+     */
+    pub fn is_locked(&self) -> bool {
+        self.cpu.is_locked()
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.cpu.framebuffer()
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.cpu.set_buttons(buttons);
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    /* The controller emulated for the cartridge, see detect_mapper. */
+    pub fn mapper(&self) -> Mapper {
+        self.mapper
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.has_battery()
+    }
+
+    /* Battery backed RAM, followed by the 48 bytes RTC footer on MBC3 cartridges with a clock.
+     * None when the cartridge has no battery.
+     */
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        match self.has_battery() {
+            true => Some(self.cpu.save_data()),
+            false => None,
+        }
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        if self.has_battery() {
+            self.cpu.load_save_data(data);
+        }
+    }
+
+    /* Loads a save file, a missing one isn't an error as the game simply wasn't saved yet. */
+    pub fn load_save(&mut self, save_path: &path::Path) -> io::Result<()> {
+        if !self.has_battery() {
+            return Ok(());
+        }
+
+        match fs::read(save_path) {
+            Ok(data) => {
+                self.load_save_data(&data);
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /* Writes to a temporary file first, so a crash while writing doesn't lose the previous save. */
+    pub fn write_save(&mut self, save_path: &path::Path) -> io::Result<()> {
+        if let Some(data) = self.save_data() {
+            let temp_path = save_path.with_extension("sav.tmp");
+            fs::write(&temp_path, data)?;
+            fs::rename(&temp_path, save_path)?;
+        }
+
+        Ok(())
+    }
+
+    /* Whether the game changed the battery backed RAM or clock since the last call, to know when
+     * the save needs flushing.
+     */
+    pub fn take_save_dirty(&mut self) -> bool {
+        self.cpu.take_save_dirty() && self.has_battery()
+    }
+
+    /* The cartridge RTC normally only counts emulated time. This advances it by the host time
+     * elapsed since it was last saved, as if the cartridge battery kept it running meanwhile.
+     */
+    pub fn sync_rtc_to_host(&mut self) {
+        self.cpu.sync_rtc_to_host();
+    }
+
+    /* Tilts the console, in g (1.0 being a quarter turn) with positive x to the right and positive y
+     * towards the player. Only cartridges with an accelerometer (MBC7) see it.
+     */
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.cpu.set_tilt(x, y);
+    }
+
+    /* Where the camera sensor takes its pictures from, TestPattern by default. Returns false if the
+     * cartridge has no camera.
+     */
+    pub fn set_camera_source(&mut self, source: Box<dyn ImageSource>) -> bool {
+        match self.cpu.camera() {
+            Some(camera) => {
+                camera.set_source(source);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /* Whether the cartridge rumble motor is on, always false for cartridges without one. Poll it
+     * after each frame.
+     */
+    pub fn is_rumbling(&self) -> bool {
+        self.cpu.is_rumbling()
+    }
+
+    /* Links the infrared ports of both cartridges, as when pointing two consoles at each other.
+     * Returns false, leaving both untouched, if either cartridge has no IR port.
+     */
+    pub fn connect_infrared(&mut self, other: &mut GameBoy) -> bool {
+        match (self.cpu.infrared(), other.cpu.infrared()) {
+            (Some(infrared), Some(other_infrared)) => {
+                infrared.connect(other_infrared);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn read_memory(&self, addr: u16) -> u8 {
+        self.cpu.read_memory(addr)
+    }
+
+    pub fn write_memory(&mut self, addr: u16, value: u8) {
+        self.cpu.write_memory(addr, value);
+    }
+
+    pub fn registers(&self) -> &Registers {
+        self.cpu.registers()
+    }
+
+    /* Logs every executed instruction, along with the registers, to the given writer. */
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.cpu.set_trace(trace);
+    }
+
+    /* The bytes sent through the serial port since the last call. Only the last 64KB are kept when
+     * nobody takes them.
+     */
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.cpu.take_serial_output()
+    }
+}
+
+/* Where the battery save of a ROM goes: the ROM path with a .sav extension, in save_dir if given. */
+pub fn save_path(rom_path: &path::Path, save_dir: Option<&path::Path>) -> path::PathBuf {
+    let save_path = rom_path.with_extension("sav");
+
+    match (save_dir, save_path.file_name()) {
+        (Some(save_dir), Some(file_name)) => save_dir.join(file_name),
+        _ => save_path,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /* A ROM only cartridge running the given code from its entry point. */
+    fn rom_with_code(code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        rom
+    }
+
+    /* An MBC3 cartridge with a clock and 8KB of battery backed RAM. */
+    fn mbc3_timer_gameboy() -> GameBoy {
+        let mut rom = rom_with_code(&[]);
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x02;
+
+        let mut gameboy = GameBoy::with_boot_rom(rom, BootRom::Skip).unwrap();
+        /* Enables the RAM and the RTC. */
+        gameboy.write_memory(0x0000, 0x0A);
+        gameboy
+    }
+
+    /* Latches the clock and reads one of its 0x08-0x0C registers. */
+    fn read_rtc(gameboy: &mut GameBoy, reg: u8) -> u8 {
+        gameboy.write_memory(0x6000, 0x00);
+        gameboy.write_memory(0x6000, 0x01);
+        gameboy.write_memory(0x4000, reg);
+        gameboy.read_memory(0xA000)
+    }
+
+    #[test]
+    fn save_path_next_to_rom() {
+        assert_eq!(save_path(path::Path::new("roms/tetris.gb"), None), path::Path::new("roms/tetris.sav"));
+        assert_eq!(save_path(path::Path::new("roms/game"), None), path::Path::new("roms/game.sav"));
+        assert_eq!(
+            save_path(path::Path::new("roms/tetris.gb.zip"), Some(path::Path::new("saves"))),
+            path::Path::new("saves/tetris.gb.sav")
+        );
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let save_path = std::env::temp_dir().join(format!("gameboy-rs-test-{}.sav", std::process::id()));
+
+        let mut gameboy = mbc3_timer_gameboy();
+        gameboy.write_memory(0x4000, 0x00);
+        gameboy.write_memory(0xA123, 0x42);
+        gameboy.write_memory(0x4000, 0x0A);
+        gameboy.write_memory(0xA000, 13);
+        gameboy.write_save(&save_path).unwrap();
+        assert!(!save_path.with_extension("sav.tmp").exists());
+
+        let mut loaded = mbc3_timer_gameboy();
+        loaded.load_save(&save_path).unwrap();
+        fs::remove_file(&save_path).unwrap();
+
+        assert_eq!(loaded.save_data().unwrap().len(), 0x2000 + 48);
+        assert_eq!(read_rtc(&mut loaded, 0x0A), 13);
+        loaded.write_memory(0x4000, 0x00);
+        assert_eq!(loaded.read_memory(0xA123), 0x42);
+    }
+
+    #[test]
+    fn short_rtc_footer() {
+        let mut data = vec![0; 0x2000 + 44];
+        data[0x10] = 0x42;
+        /* Hours, then the latched minutes. */
+        data[0x2000 + 8] = 5;
+        data[0x2000 + 24] = 30;
+
+        let mut gameboy = mbc3_timer_gameboy();
+        gameboy.load_save_data(&data);
+
+        gameboy.write_memory(0x4000, 0x09);
+        assert_eq!(gameboy.read_memory(0xA000), 30);
+        assert_eq!(read_rtc(&mut gameboy, 0x0A), 5);
+        gameboy.write_memory(0x4000, 0x00);
+        assert_eq!(gameboy.read_memory(0xA010), 0x42);
+    }
+
+    #[test]
+    fn custom_boot_rom_size() {
+        assert!(GameBoy::with_boot_rom(rom_with_code(&[]), BootRom::Custom(vec![0; BOOT_ROM_SIZE])).is_ok());
+
+        assert_eq!(
+            GameBoy::with_boot_rom(rom_with_code(&[]), BootRom::Custom(vec![0; 0x900])).err(),
+            Some(GameBoyError::BootRomSize { len: 0x900, expected: BOOT_ROM_SIZE })
+        );
+    }
+
+    #[test]
+    fn illegal_opcode_locks_cpu() {
+        let mut gameboy = GameBoy::with_boot_rom(rom_with_code(&[0xD3]), BootRom::Skip).unwrap();
+        let div = gameboy.read_memory(0xFF04);
+
+        for _ in 0..1000 {
+            gameboy.step_instruction();
+        }
+
+        assert!(gameboy.is_locked());
+        assert_eq!(gameboy.registers().pc, 0x0101);
+        /* The timer keeps running. */
+        assert_ne!(gameboy.read_memory(0xFF04), div);
+    }
+}
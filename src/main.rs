@@ -1,47 +1,193 @@
-use std::path;
-use std::time::{Duration, Instant};
+#![allow(clippy::upper_case_acronyms)]
+
+use std::env;
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
 use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-mod cpu;
-mod mbc;
-mod mmu;
-mod registers;
-mod ppu;
-mod decode;
+use gameboy_rs::{
+    BootRom, CartridgeHeader, GameBoy, GameBoyError, StillImage, CYCLES_PER_FRAME, CYCLES_PER_SECOND,
+};
+
+/* Flush the battery save at most once a second of emulated time. */
+const SAVE_FLUSH_FRAMES: usize = 60;
+
+mod cli;
+#[cfg(feature = "window")]
 mod lcd;
-mod palette;
-mod joypad;
-mod timer;
 
 fn main() {
-    let start = Instant::now();
-    let rom_path = path::Path::new("/home/martin/Documents/gameboy-rs/roms/Tetris.GB");
-
-    let mut cpu = cpu::CPU::new(rom_path);
-
-    let mut cycles_count = 0;
-    let mut prev = Instant::now();
-    let one_sec = Duration::from_millis(1);
-
-    loop {
-        cpu.do_cycle();
-
-
-        // if cycles_count > 4 * 10u32.pow(0) {
-        //     println!("elapsed {}ms", start.elapsed().as_micros());
-        //
-        //
-        //     let diff = Instant::now().duration_since(prev);
-        //     if diff > one_sec {
-        //         eprintln!("running late");
-        //     } else {
-        //         let remaining = one_sec - diff;
-        //         sleep(remaining);
-        //     }
-        //
-        //
-        //     prev =  Instant::now();
-        //     cycles_count = 0;
-        // }
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(options: cli::Options) -> Result<(), String> {
+    let mut rom = gameboy_rs::read_rom_file(&options.rom_path, options.entry.as_deref())
+        .map_err(|err| format!("{}: {}", options.rom_path.display(), err))?;
+
+    let patch_path = match options.no_patch {
+        true => None,
+        false => options.patch.clone().or_else(|| gameboy_rs::find_patch(&options.rom_path)),
+    };
+
+    if let Some(ref patch_path) = patch_path {
+        let patch = read_file(patch_path)?;
+        rom = gameboy_rs::apply_patch(&rom, &patch).map_err(|err| format!("{}: {}", patch_path.display(), err))?;
     }
+
+    if options.info {
+        return print_cartridge_info(&rom);
+    }
+
+    let boot_rom = match options.boot_rom {
+        Some(ref path) => BootRom::Custom(read_file(path)?),
+        None if options.skip_boot => BootRom::Skip,
+        None => BootRom::Dmg,
+    };
+
+    let mut gameboy = GameBoy::with_boot_rom(rom, boot_rom).map_err(|err| {
+        let path = match (&err, &options.boot_rom) {
+            (GameBoyError::BootRomSize { .. }, Some(boot_rom_path)) => boot_rom_path,
+            _ => &options.rom_path,
+        };
+        format!("{}: {}", path.display(), err)
+    })?;
+
+    let save_path = gameboy_rs::save_path(&options.rom_path, options.save_dir.as_deref());
+    gameboy
+        .load_save(&save_path)
+        .map_err(|err| format!("{}: {}", save_path.display(), err))?;
+
+    if options.rtc_sync {
+        gameboy.sync_rtc_to_host();
+    }
+
+    if let Some(ref path) = options.camera_image {
+        let image = StillImage::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        if !gameboy.set_camera_source(Box::new(image)) {
+            eprintln!("warning: the cartridge has no camera, ignoring {}", path.display());
+        }
+    }
+
+    if let Some(ref path) = options.trace {
+        let trace = fs::File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        gameboy.set_trace(Box::new(BufWriter::new(trace)));
+    }
+
+    #[cfg(feature = "window")]
+    let mut lcd = match options.headless {
+        true => None,
+        false => Some(lcd::LCD::new(options.scale).map_err(|err| format!("can't open window: {}", err))?),
+    };
+
+    #[cfg(not(feature = "window"))]
+    {
+        if !options.headless {
+            return Err(String::from("built without the window feature, only --headless is available"));
+        }
+    }
+
+    let frame_duration = match options.speed {
+        speed if speed > 0.0 => {
+            Some(Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / CYCLES_PER_SECOND as f64 / speed))
+        }
+        _ => None,
+    };
+    let mut next_frame = Instant::now();
+    let mut frames = 0;
+
+    while options.frames != Some(frames) {
+        gameboy.run_frame();
+        frames += 1;
+
+        if frames % SAVE_FLUSH_FRAMES == 0 && gameboy.take_save_dirty() {
+            if let Err(err) = gameboy.write_save(&save_path) {
+                eprintln!("warning: {}: {}", save_path.display(), err);
+            }
+        }
+
+        #[cfg(feature = "window")]
+        {
+            if let Some(ref mut lcd) = lcd {
+                lcd.draw(gameboy.framebuffer());
+                gameboy.set_buttons(lcd.get_buttons());
+
+                let (x, y) = lcd.get_tilt();
+                gameboy.set_tilt(x, y);
+
+                if !lcd.is_open() {
+                    break;
+                }
+            }
+        }
+
+        if let Some(frame_duration) = frame_duration {
+            next_frame += frame_duration;
+
+            let now = Instant::now();
+            if next_frame > now {
+                sleep(next_frame - now);
+            } else {
+                /* Running late, don't try to catch up. */
+                next_frame = now;
+            }
+        }
+    }
+
+    /* Always written on exit so the RTC footer records when the emulator stopped. */
+    gameboy
+        .write_save(&save_path)
+        .map_err(|err| format!("{}: {}", save_path.display(), err))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn print_cartridge_info(rom: &[u8]) -> Result<(), String> {
+    let header = CartridgeHeader::parse(rom).map_err(|err| err.to_string())?;
+    let mapper = match gameboy_rs::detect_mapper(&header, rom) {
+        Ok(mapper) => mapper.to_string(),
+        Err(err) => err.to_string(),
+    };
+    let checksum = |valid| match valid {
+        true => "OK",
+        false => "BAD",
+    };
+
+    println!("Title:            {}", header.title);
+    if let Some(ref manufacturer_code) = header.manufacturer_code {
+        println!("Manufacturer:     {}", manufacturer_code);
+    }
+    println!("Licensee:         {}", header.licensee());
+    println!("CGB support:      {:?}", header.cgb_support);
+    println!("SGB support:      {}", header.sgb_support);
+    println!("Cartridge type:   {}", header.cartridge_type);
+    println!("Mapper:           {}", mapper);
+    println!("ROM size:         {} KB", header.rom_size / 1024);
+    println!("RAM size:         {} KB", header.ram_size / 1024);
+    println!("Destination:      {:?}", header.destination);
+    println!("Version:          {}", header.version);
+    println!("Header checksum:  {:02X} {}", header.header_checksum, checksum(header.is_header_checksum_valid()));
+    println!("Global checksum:  {:04X} {}", header.global_checksum, checksum(header.is_global_checksum_valid()));
+
+    Ok(())
 }
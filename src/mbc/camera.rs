@@ -0,0 +1,158 @@
+use std::mem;
+
+use crate::camera::{Camera, PICTURE_SIZE};
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+
+/* RAM bank register bit mapping the camera registers at 0xA000-0xBFFF instead of the RAM. */
+const CAMERA_REGISTERS: u8 = 0x10;
+
+/* The sensor registers are mirrored every 0x80 bytes. */
+const REGISTER_MASK: u16 = 0x7F;
+
+/* Where the developed picture goes, in RAM bank 0. */
+const PICTURE_OFFSET: usize = 0x0100;
+
+/* Nintendo Pocket Camera, a MBC3 like controller driving the M64282FP sensor. */
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    camera: Camera,
+    /* Only gates RAM writes, the RAM can always be read. */
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl PocketCamera {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> PocketCamera {
+        PocketCamera {
+            rom,
+            ram: vec![0; ram_size],
+            camera: Camera::new(),
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn is_camera_mapped(&self) -> bool {
+        self.ram_bank & CAMERA_REGISTERS != 0
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        banked_ram_offset(self.ram.len(), self.ram_bank as usize, addr)
+    }
+}
+
+impl MBC for PocketCamera {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid Pocket Camera ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if self.is_camera_mapped() {
+            return self.camera.read_reg((addr & REGISTER_MASK) as usize);
+        }
+
+        /* The RAM is busy while the sensor is working. */
+        if self.camera.is_busy() {
+            return 0x00;
+        }
+
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = val & 0x0F == 0x0A,
+            /* Bank 0 can be mapped at 0x4000 too. */
+            0x2000..=0x3FFF => self.rom_bank = val & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = val & 0x1F,
+            0x6000..=0x7FFF => {}
+            _ => panic!("Invalid Pocket Camera register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        if self.is_camera_mapped() {
+            self.camera.write_reg((addr & REGISTER_MASK) as usize, val);
+            return false;
+        }
+
+        if !self.ram_enabled || self.camera.is_busy() {
+            return false;
+        }
+
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn do_cycle(&mut self) {
+        if self.camera.do_cycle() && self.ram.len() >= PICTURE_OFFSET + PICTURE_SIZE {
+            self.camera.develop(&mut self.ram[PICTURE_OFFSET..PICTURE_OFFSET + PICTURE_SIZE]);
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn take_picture() {
+        let mut mbc = PocketCamera::new(vec![0; 2 * ROM_BANK_SIZE], 0x20000);
+
+        /* Exposure of 0x0300, every matrix threshold at 0x80, positive output. */
+        mbc.write_rom(0x4000, CAMERA_REGISTERS);
+        mbc.write_ram(0xA002, 0x03);
+        for reg in 0x06..0x36 {
+            mbc.write_ram(0xA000 + reg, 0x80);
+        }
+        mbc.write_ram(0xA000, 0x03);
+        assert_eq!(mbc.read_ram(0xA000), 0x03);
+        /* Mirrored. */
+        assert_eq!(mbc.read_ram(0xA080), 0x03);
+
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA100), 0x00);
+
+        while mbc.camera.is_busy() {
+            mbc.do_cycle();
+        }
+
+        mbc.write_rom(0x4000, CAMERA_REGISTERS);
+        assert_eq!(mbc.read_ram(0xA000), 0x02);
+
+        /* The first tile is the dark end of the test pattern gradient, the last one of the first row
+         * the light end.
+         */
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA100), 0xFF);
+        assert_eq!(mbc.read_ram(0xA101), 0xFF);
+        assert_eq!(mbc.read_ram(0xA100 + 15 * 16), 0x00);
+        assert_eq!(mbc.read_ram(0xA101 + 15 * 16), 0x00);
+    }
+}
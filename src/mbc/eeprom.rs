@@ -0,0 +1,241 @@
+/* 93LC56 serial EEPROM, 128 16 bits words, bit-banged by the game through the MBC7 register. */
+
+pub const EEPROM_SIZE: usize = 256;
+const WORDS: usize = EEPROM_SIZE / 2;
+
+/* Bits of the MBC7 EEPROM register. */
+const CHIP_SELECT: u8 = 0x80;
+const CLOCK: u8 = 0x40;
+const DATA_IN: u8 = 0x02;
+const DATA_OUT: u8 = 0x01;
+
+/* Start bit, 2 bits opcode and 8 bits address (the top one is unused). */
+const COMMAND_BITS: u8 = 11;
+const WORD_BITS: u8 = 16;
+
+const OPCODE_EXTENDED: u16 = 0b00;
+const OPCODE_WRITE: u16 = 0b01;
+const OPCODE_READ: u16 = 0b10;
+const OPCODE_ERASE: u16 = 0b11;
+
+/* Extended commands, given by the 2 top address bits. */
+const WRITE_DISABLE: u16 = 0b00;
+const WRITE_ALL: u16 = 0b01;
+const ERASE_ALL: u16 = 0b10;
+const WRITE_ENABLE: u16 = 0b11;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /* Waiting for the start bit. */
+    Idle,
+    /* Shifting in the opcode and address, the start bit included. */
+    Command { bits: u16, count: u8 },
+    Reading { word: u16, count: u8 },
+    /* None writes every word (WRAL). */
+    Writing { addr: Option<usize>, word: u16, count: u8 },
+}
+
+pub struct Eeprom {
+    words: [u16; WORDS],
+    write_enabled: bool,
+    state: State,
+    /* Lines as last written by the game, plus the output one. */
+    lines: u8,
+    data_out: bool,
+}
+
+impl Eeprom {
+    pub fn new() -> Eeprom {
+        Eeprom {
+            /* Erased EEPROM cells read as 1s. */
+            words: [0xFFFF; WORDS],
+            write_enabled: false,
+            state: State::Idle,
+            lines: 0,
+            data_out: true,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        (self.lines & (CHIP_SELECT | CLOCK | DATA_IN)) | (self.data_out as u8 * DATA_OUT)
+    }
+
+    /* Returns whether a command completed by this write changed the stored words. */
+    pub fn write(&mut self, val: u8) -> bool {
+        let rising_clock = self.lines & CLOCK == 0 && val & CLOCK != 0;
+        self.lines = val;
+
+        if val & CHIP_SELECT == 0 {
+            self.state = State::Idle;
+            return false;
+        }
+
+        if !rising_clock {
+            return false;
+        }
+
+        let words = self.words;
+        self.clock_in(val & DATA_IN != 0);
+        self.words != words
+    }
+
+    fn clock_in(&mut self, bit: bool) {
+        self.state = match self.state {
+            State::Idle if bit => State::Command { bits: 1, count: 1 },
+            State::Idle => State::Idle,
+            State::Command { bits, count } => {
+                let bits = bits << 1 | bit as u16;
+
+                match count + 1 {
+                    COMMAND_BITS => self.execute(bits),
+                    count => State::Command { bits, count },
+                }
+            }
+            State::Reading { word, count } => {
+                self.data_out = word & 0x8000 != 0;
+
+                match count + 1 {
+                    WORD_BITS => State::Idle,
+                    count => State::Reading { word: word << 1, count },
+                }
+            }
+            State::Writing { addr, word, count } => {
+                let word = word << 1 | bit as u16;
+
+                match count + 1 {
+                    WORD_BITS => {
+                        if self.write_enabled {
+                            match addr {
+                                Some(addr) => self.words[addr] = word,
+                                None => self.words = [word; WORDS],
+                            }
+                        }
+                        /* Ready. */
+                        self.data_out = true;
+                        State::Idle
+                    }
+                    count => State::Writing { addr, word, count },
+                }
+            }
+        };
+    }
+
+    fn execute(&mut self, bits: u16) -> State {
+        let opcode = (bits >> 8) & 0b11;
+        let addr = bits & 0xFF;
+        let word = (addr & 0x7F) as usize;
+
+        match opcode {
+            OPCODE_READ => {
+                /* A dummy 0 bit comes before the data. */
+                self.data_out = false;
+                State::Reading { word: self.words[word], count: 0 }
+            }
+            OPCODE_WRITE => State::Writing { addr: Some(word), word: 0, count: 0 },
+            OPCODE_ERASE => {
+                if self.write_enabled {
+                    self.words[word] = 0xFFFF;
+                }
+                self.data_out = true;
+                State::Idle
+            }
+            OPCODE_EXTENDED => match addr >> 6 {
+                WRITE_DISABLE => {
+                    self.write_enabled = false;
+                    State::Idle
+                }
+                WRITE_ALL => State::Writing { addr: None, word: 0, count: 0 },
+                ERASE_ALL => {
+                    if self.write_enabled {
+                        self.words = [0xFFFF; WORDS];
+                    }
+                    self.data_out = true;
+                    State::Idle
+                }
+                WRITE_ENABLE => {
+                    self.write_enabled = true;
+                    State::Idle
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /* Words are stored little endian in save files. */
+    pub fn save(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    pub fn load(&mut self, data: &[u8]) {
+        for (word, bytes) in self.words.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn clock(eeprom: &mut Eeprom, bit: bool) {
+        let di = bit as u8 * DATA_IN;
+
+        eeprom.write(CHIP_SELECT | di);
+        eeprom.write(CHIP_SELECT | CLOCK | di);
+    }
+
+    fn send(eeprom: &mut Eeprom, bits: u32, count: u8) {
+        for i in (0..count).rev() {
+            clock(eeprom, bits >> i & 1 != 0);
+        }
+    }
+
+    fn command(eeprom: &mut Eeprom, opcode: u16, addr: u16) {
+        eeprom.write(0);
+        send(eeprom, (1 << 10 | opcode << 8 | addr) as u32, 11);
+    }
+
+    fn read_word(eeprom: &mut Eeprom, addr: u16) -> u16 {
+        command(eeprom, OPCODE_READ, addr);
+        assert_eq!(eeprom.read() & DATA_OUT, 0);
+
+        (0..WORD_BITS).fold(0, |word, _| {
+            clock(eeprom, false);
+            word << 1 | (eeprom.read() & DATA_OUT) as u16
+        })
+    }
+
+    #[test]
+    fn write_then_read() {
+        let mut eeprom = Eeprom::new();
+
+        /* Writes are ignored until enabled. */
+        command(&mut eeprom, OPCODE_WRITE, 0x05);
+        send(&mut eeprom, 0x1234, 16);
+        assert_eq!(read_word(&mut eeprom, 0x05), 0xFFFF);
+
+        command(&mut eeprom, OPCODE_EXTENDED, WRITE_ENABLE << 6);
+        command(&mut eeprom, OPCODE_WRITE, 0x05);
+        send(&mut eeprom, 0x1234, 16);
+        assert_eq!(eeprom.read() & DATA_OUT, DATA_OUT);
+        assert_eq!(read_word(&mut eeprom, 0x05), 0x1234);
+
+        command(&mut eeprom, OPCODE_ERASE, 0x05);
+        assert_eq!(read_word(&mut eeprom, 0x05), 0xFFFF);
+    }
+
+    #[test]
+    fn write_all() {
+        let mut eeprom = Eeprom::new();
+
+        command(&mut eeprom, OPCODE_EXTENDED, WRITE_ENABLE << 6);
+        command(&mut eeprom, OPCODE_EXTENDED, WRITE_ALL << 6);
+        send(&mut eeprom, 0xBEEF, 16);
+        assert_eq!(read_word(&mut eeprom, 0x7F), 0xBEEF);
+
+        let mut loaded = Eeprom::new();
+        loaded.load(&eeprom.save());
+        assert_eq!(read_word(&mut loaded, 0x00), 0xBEEF);
+    }
+}
@@ -0,0 +1,122 @@
+use std::mem;
+
+use crate::infrared::Infrared;
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+
+/* Value written to 0x0000-0x1FFF mapping the IR port at 0xA000-0xBFFF instead of the RAM. */
+const IR_MODE: u8 = 0x0E;
+
+/* Hudson HuC1, a MBC1 like controller with an infrared port. */
+pub struct HuC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    infrared: Infrared,
+    /* There is no RAM enable, the register only switches between RAM and IR. */
+    ir_mode: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl HuC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> HuC1 {
+        HuC1 {
+            rom,
+            ram: vec![0; ram_size],
+            infrared: Infrared::new(),
+            ir_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        banked_ram_offset(self.ram.len(), self.ram_bank as usize, addr)
+    }
+}
+
+impl MBC for HuC1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid HuC1 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if self.ir_mode {
+            return self.infrared.read();
+        }
+
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ir_mode = val & 0x0F == IR_MODE,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match val & 0x3F {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = val & 0x03,
+            0x6000..=0x7FFF => {}
+            _ => panic!("Invalid HuC1 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        if self.ir_mode {
+            self.infrared.write(val);
+            return false;
+        }
+
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn infrared(&mut self) -> Option<&mut Infrared> {
+        Some(&mut self.infrared)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn ir_mode() {
+        let mut mbc = HuC1::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        let mut remote = Infrared::new();
+        mbc.infrared.connect(&mut remote);
+
+        mbc.write_rom(0x0000, IR_MODE);
+        assert_eq!(mbc.read_ram(0xA000), 0xC0);
+        mbc.write_ram(0xA000, 0x01);
+        assert_eq!(remote.read(), 0xC1);
+
+        /* The LED write didn't go to the RAM. */
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+    }
+}
@@ -0,0 +1,437 @@
+use std::mem;
+
+use crate::infrared::Infrared;
+use crate::mbc::rtc::Rtc;
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+use crate::CYCLES_PER_SECOND;
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+/* Both the minute and day counters are 12 bits wide. */
+const COUNTER_WRAP: u64 = 0x1000;
+
+/* RTC state appended to the RAM in save files: minutes and days as little endian u32, the u64 unix
+ * timestamp of the save, then the RTC memory with two nibbles per byte, lowest address in the low
+ * nibble. Older saves without the memory still load.
+ */
+const RTC_MEMORY_SIZE: usize = 256;
+const RTC_TIME_SAVE_SIZE: usize = 16;
+const RTC_SAVE_SIZE: usize = RTC_TIME_SAVE_SIZE + RTC_MEMORY_SIZE / 2;
+
+/* What 0xA000-0xBFFF maps, selected by the lower nibble of a 0x0000-0x1FFF write. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    RamReadOnly,
+    RamReadWrite,
+    /* Sends a command and its argument to the RTC. */
+    RtcCommand,
+    RtcResponse,
+    /* Tells whether the RTC is done with the last command. */
+    RtcSemaphore,
+    Infrared,
+    Unmapped,
+}
+
+/* RTC commands, upper nibble of the value written in RtcCommand mode. */
+const READ_AND_INCREMENT: u8 = 0x1;
+const WRITE_AND_INCREMENT: u8 = 0x3;
+const SET_ADDRESS_LOW: u8 = 0x4;
+const SET_ADDRESS_HIGH: u8 = 0x5;
+const EXTENDED: u8 = 0x6;
+
+/* Arguments of the extended command. */
+const COPY_TIME_TO_MEMORY: u8 = 0x0;
+const COPY_MEMORY_TO_TIME: u8 = 0x1;
+const STATUS: u8 = 0x2;
+
+/* The HuC3 RTC isn't register based like MBC3's. The game talks to it with 4 bits commands,
+ * through 256 nibbles of memory where the time gets copied to and from: minutes of the day at
+ * 0x00-0x02 and days at 0x03-0x05, least significant nibble first.
+ */
+struct HuC3Rtc {
+    minutes: u16,
+    days: u16,
+    /* Cycles elapsed in the current minute. */
+    cycles: usize,
+
+    memory: [u8; RTC_MEMORY_SIZE],
+    address: u8,
+    command: u8,
+    response: u8,
+
+    timestamp: u64,
+}
+
+impl HuC3Rtc {
+    fn new() -> HuC3Rtc {
+        HuC3Rtc {
+            minutes: 0,
+            days: 0,
+            cycles: 0,
+
+            memory: [0; RTC_MEMORY_SIZE],
+            address: 0,
+            command: 0,
+            response: 0,
+
+            timestamp: Rtc::now(),
+        }
+    }
+
+    /* Returns whether the command changed the clock or the RTC memory, which are saved. */
+    fn write_command(&mut self, val: u8) -> bool {
+        let command = (val >> 4) & 0x07;
+        let arg = val & 0x0F;
+
+        self.command = command;
+
+        match command {
+            READ_AND_INCREMENT => {
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+                false
+            }
+            WRITE_AND_INCREMENT => {
+                let changed = mem::replace(&mut self.memory[self.address as usize], arg) != arg;
+                self.address = self.address.wrapping_add(1);
+                changed
+            }
+            SET_ADDRESS_LOW => {
+                self.address = (self.address & 0xF0) | arg;
+                false
+            }
+            SET_ADDRESS_HIGH => {
+                self.address = (self.address & 0x0F) | arg << 4;
+                false
+            }
+            EXTENDED => match arg {
+                COPY_TIME_TO_MEMORY => {
+                    let minutes_changed = self.write_counter(0x00, self.minutes);
+                    let days_changed = self.write_counter(0x03, self.days);
+                    minutes_changed || days_changed
+                }
+                COPY_MEMORY_TO_TIME => {
+                    let time = (self.read_counter(0x00), self.read_counter(0x03));
+                    let changed = time != (self.minutes, self.days);
+
+                    self.minutes = time.0;
+                    self.days = time.1;
+                    self.cycles = 0;
+                    changed
+                }
+                STATUS => {
+                    self.response = 0x01;
+                    false
+                }
+                /* Tone generation on the cartridge speaker, not emulated. */
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn read_response(&self) -> u8 {
+        0x80 | self.command << 4 | self.response
+    }
+
+    /* Returns whether the memory changed. */
+    fn write_counter(&mut self, start: usize, counter: u16) -> bool {
+        let mut changed = false;
+
+        for i in 0..3 {
+            let nibble = (counter >> (4 * i)) as u8 & 0x0F;
+            changed |= mem::replace(&mut self.memory[start + i], nibble) != nibble;
+        }
+
+        changed
+    }
+
+    fn read_counter(&self, start: usize) -> u16 {
+        (0..3).fold(0, |counter, i| counter | (self.memory[start + i] as u16) << (4 * i))
+    }
+
+    fn do_cycle(&mut self) {
+        self.cycles += 1;
+
+        if self.cycles == 60 * CYCLES_PER_SECOND {
+            self.cycles = 0;
+            self.advance_minutes(1);
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.minutes as u64 + minutes;
+
+        self.minutes = (total % MINUTES_PER_DAY) as u16;
+        self.days = ((self.days as u64 + total / MINUTES_PER_DAY) % COUNTER_WRAP) as u16;
+    }
+
+    fn sync_to_host(&mut self) {
+        let now = Rtc::now();
+        let elapsed = now.saturating_sub(self.timestamp);
+
+        /* Keep the leftover seconds for the next minute. */
+        let cycles = self.cycles as u64 + elapsed % 60 * CYCLES_PER_SECOND as u64;
+        self.cycles = (cycles % (60 * CYCLES_PER_SECOND as u64)) as usize;
+        self.advance_minutes(elapsed / 60 + cycles / (60 * CYCLES_PER_SECOND as u64));
+
+        self.timestamp = now;
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+
+        data.extend_from_slice(&(self.minutes as u32).to_le_bytes());
+        data.extend_from_slice(&(self.days as u32).to_le_bytes());
+        data.extend_from_slice(&Rtc::now().to_le_bytes());
+        data.extend(self.memory.chunks(2).map(|nibbles| nibbles[0] | nibbles[1] << 4));
+
+        data
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        if data.len() != RTC_SAVE_SIZE && data.len() != RTC_TIME_SAVE_SIZE {
+            return;
+        }
+
+        let u32_at = |pos: usize| u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&data[8..RTC_TIME_SAVE_SIZE]);
+
+        self.minutes = (u32_at(0) as u64 % MINUTES_PER_DAY) as u16;
+        self.days = (u32_at(4) as u64 % COUNTER_WRAP) as u16;
+        self.timestamp = u64::from_le_bytes(timestamp);
+
+        for (i, &byte) in data[RTC_TIME_SAVE_SIZE..].iter().enumerate() {
+            self.memory[2 * i] = byte & 0x0F;
+            self.memory[2 * i + 1] = byte >> 4;
+        }
+    }
+}
+
+/* Hudson HuC3, with an RTC, an infrared port and a speaker. */
+pub struct HuC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: HuC3Rtc,
+    infrared: Infrared,
+    mode: Mode,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl HuC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> HuC3 {
+        HuC3 {
+            rom,
+            ram: vec![0; ram_size],
+            rtc: HuC3Rtc::new(),
+            infrared: Infrared::new(),
+            mode: Mode::RamReadOnly,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        banked_ram_offset(self.ram.len(), self.ram_bank as usize, addr)
+    }
+}
+
+impl MBC for HuC3 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid HuC3 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.mode {
+            Mode::RamReadOnly | Mode::RamReadWrite => match self.ram_offset(addr) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+            Mode::RtcResponse => self.rtc.read_response(),
+            /* Commands are executed right away, the RTC is always ready. */
+            Mode::RtcSemaphore => 0x01,
+            Mode::Infrared => self.infrared.read(),
+            Mode::RtcCommand | Mode::Unmapped => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.mode = match val & 0x0F {
+                    0x0 => Mode::RamReadOnly,
+                    0xA => Mode::RamReadWrite,
+                    0xB => Mode::RtcCommand,
+                    0xC => Mode::RtcResponse,
+                    0xD => Mode::RtcSemaphore,
+                    0xE => Mode::Infrared,
+                    _ => Mode::Unmapped,
+                };
+            }
+            0x2000..=0x3FFF => {
+                self.rom_bank = match val & 0x7F {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = val & 0x03,
+            0x6000..=0x7FFF => {}
+            _ => panic!("Invalid HuC3 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        match self.mode {
+            Mode::RamReadWrite => match self.ram_offset(addr) {
+                Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+                None => false,
+            },
+            /* The clock and the RTC memory are saved along with the RAM. */
+            Mode::RtcCommand => self.rtc.write_command(val),
+            Mode::Infrared => {
+                self.infrared.write(val);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn do_cycle(&mut self) {
+        self.rtc.do_cycle();
+    }
+
+    fn sync_rtc_to_host(&mut self) {
+        self.rtc.sync_to_host();
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        data.extend(self.rtc.save());
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+        self.rtc.load(&data[len..]);
+    }
+
+    fn infrared(&mut self) -> Option<&mut Infrared> {
+        Some(&mut self.infrared)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::ROM_BANK_SIZE;
+
+    fn command(mbc: &mut HuC3, command: u8, arg: u8) -> bool {
+        mbc.write_ram(0xA000, command << 4 | arg)
+    }
+
+    fn read_counter(mbc: &mut HuC3, address: u8) -> u16 {
+        command(mbc, SET_ADDRESS_LOW, address & 0x0F);
+        command(mbc, SET_ADDRESS_HIGH, address >> 4);
+
+        (0..3).fold(0, |counter, i| {
+            mbc.write_rom(0x0000, 0x0B);
+            command(mbc, READ_AND_INCREMENT, 0);
+            mbc.write_rom(0x0000, 0x0C);
+            counter | ((mbc.read_ram(0xA000) & 0x0F) as u16) << (4 * i)
+        })
+    }
+
+    #[test]
+    fn set_and_read_time() {
+        let mut mbc = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+
+        mbc.write_rom(0x0000, 0x0B);
+        command(&mut mbc, SET_ADDRESS_LOW, 0);
+        command(&mut mbc, SET_ADDRESS_HIGH, 0);
+        /* 0x59F minutes (23:59), then 0x123 days. */
+        for &nibble in &[0xF, 0x9, 0x5, 0x3, 0x2, 0x1] {
+            command(&mut mbc, WRITE_AND_INCREMENT, nibble);
+        }
+        command(&mut mbc, EXTENDED, COPY_MEMORY_TO_TIME);
+
+        /* Skip to the end of the minute. */
+        mbc.rtc.cycles = 60 * CYCLES_PER_SECOND - 1;
+        mbc.do_cycle();
+
+        mbc.write_rom(0x0000, 0x0B);
+        command(&mut mbc, EXTENDED, COPY_TIME_TO_MEMORY);
+        assert_eq!(read_counter(&mut mbc, 0x00), 0);
+        assert_eq!(read_counter(&mut mbc, 0x03), 0x124);
+
+        mbc.write_rom(0x0000, 0x0D);
+        assert_eq!(mbc.read_ram(0xA000), 0x01);
+    }
+
+    #[test]
+    fn ram_modes() {
+        let mut mbc = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        mbc.write_rom(0x0000, 0x0E);
+        assert_eq!(mbc.read_ram(0xA000), 0xC0);
+    }
+
+    #[test]
+    fn rtc_saved() {
+        let mut mbc = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+        mbc.rtc.minutes = 100;
+        mbc.rtc.days = 7;
+        mbc.rtc.memory[0x10] = 0x3;
+        mbc.rtc.memory[0xFF] = 0xC;
+
+        let data = mbc.save_data();
+        assert_eq!(data.len(), 0x2000 + RTC_SAVE_SIZE);
+
+        let mut loaded = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+        loaded.load_save_data(&data);
+        assert_eq!((loaded.rtc.minutes, loaded.rtc.days), (100, 7));
+        assert_eq!(&loaded.rtc.memory[..], &mbc.rtc.memory[..]);
+
+        /* Saves from before the RTC memory was saved. */
+        let mut loaded = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+        loaded.load_save_data(&data[..0x2000 + RTC_TIME_SAVE_SIZE]);
+        assert_eq!((loaded.rtc.minutes, loaded.rtc.days), (100, 7));
+        assert_eq!(loaded.rtc.memory[0x10], 0);
+    }
+
+    #[test]
+    fn commands_change_save() {
+        let mut mbc = HuC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000);
+        mbc.write_rom(0x0000, 0x0B);
+
+        assert!(!command(&mut mbc, SET_ADDRESS_LOW, 0x0));
+        assert!(!command(&mut mbc, SET_ADDRESS_HIGH, 0x1));
+        assert!(command(&mut mbc, WRITE_AND_INCREMENT, 0x5));
+        assert!(!command(&mut mbc, SET_ADDRESS_LOW, 0x0));
+        assert!(!command(&mut mbc, WRITE_AND_INCREMENT, 0x5));
+        assert!(!command(&mut mbc, READ_AND_INCREMENT, 0));
+        assert!(!command(&mut mbc, EXTENDED, STATUS));
+
+        assert!(!command(&mut mbc, EXTENDED, COPY_TIME_TO_MEMORY));
+        assert!(!command(&mut mbc, EXTENDED, COPY_MEMORY_TO_TIME));
+        mbc.rtc.minutes = 42;
+        assert!(command(&mut mbc, EXTENDED, COPY_TIME_TO_MEMORY));
+        assert!(!command(&mut mbc, EXTENDED, COPY_MEMORY_TO_TIME));
+        mbc.rtc.days = 3;
+        assert!(command(&mut mbc, EXTENDED, COPY_MEMORY_TO_TIME));
+        assert_eq!(mbc.rtc.days, 0);
+    }
+}
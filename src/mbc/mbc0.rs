@@ -1,28 +1,72 @@
-use crate::mbc::MBC;
+use std::mem;
 
+use crate::mbc::{banked_ram_offset, MBC};
+
+/* No controller, a 32KB ROM and optionally up to 8KB of RAM wired directly to the bus. */
 pub struct MBC0 {
     rom: Vec<u8>,
+    ram: Vec<u8>,
 }
 
 impl MBC0 {
-    pub fn new(rom: Vec<u8>) -> MBC0 {
-        MBC0 { rom }
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> MBC0 {
+        MBC0 {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        banked_ram_offset(self.ram.len(), 0, addr)
     }
 }
 
 impl MBC for MBC0 {
+    /* Dumps and homebrew smaller than 32KB leave the rest of the bus floating. */
     fn read_rom(&self, addr: u16) -> u8 {
         match self.rom.get(addr as usize) {
             Some(byte) => *byte,
-            None => panic!("Out of bounds access on MBC0 ROM at address {:4X}", addr),
+            None => 0xFF,
         }
     }
 
-    fn read_ram(&self, _addr: u16) -> u8 {
-        0
+    /* There is no enable register, the RAM is always accessible. */
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
     }
 
     fn write_rom(&mut self, _addr: u16, _val: u8) {}
 
-    fn write_ram(&mut self, _addr: u16, _val: u8) {}
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_rom() {
+        let mbc = MBC0::new(vec![0x42; 0x4000], 0);
+
+        assert_eq!(mbc.read_rom(0x3FFF), 0x42);
+        assert_eq!(mbc.read_rom(0x4000), 0xFF);
+        assert_eq!(mbc.read_rom(0x7FFF), 0xFF);
+    }
 }
@@ -0,0 +1,201 @@
+use std::mem;
+
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+
+pub struct MBC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    /* 5 bits register, lower bits of the 0x4000-0x7FFF bank. */
+    bank1: u8,
+    /* 2 bits register, upper bits of the ROM bank or RAM bank depending on the mode. */
+    bank2: u8,
+    /* In mode 1, bank2 also applies to 0x0000-0x3FFF and selects the RAM bank. */
+    advanced_banking: bool,
+    /* MBC1M wires only 4 bits of bank1 to the ROM, bank2 selects one of the 256KB games. */
+    multicart: bool,
+}
+
+impl MBC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, multicart: bool) -> MBC1 {
+        MBC1 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    fn bank2_shift(&self) -> u8 {
+        match self.multicart {
+            true => 4,
+            false => 5,
+        }
+    }
+
+    fn rom_bank_low(&self) -> usize {
+        match self.advanced_banking {
+            true => (self.bank2 << self.bank2_shift()) as usize,
+            false => 0,
+        }
+    }
+
+    fn rom_bank_high(&self) -> usize {
+        let bank1 = match self.multicart {
+            true => self.bank1 & 0x0F,
+            false => self.bank1,
+        };
+
+        ((self.bank2 << self.bank2_shift()) | bank1) as usize
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled {
+            return None;
+        }
+
+        let bank = match self.advanced_banking {
+            true => self.bank2 as usize,
+            false => 0,
+        };
+
+        banked_ram_offset(self.ram.len(), bank, addr)
+    }
+}
+
+impl MBC for MBC1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, self.rom_bank_low(), addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank_high(), addr),
+            _ => panic!("Invalid MBC1 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = val & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                /* Bank 0 can't be mapped at 0x4000, it gets remapped to bank 1. As the check is done
+                 * on the 5 bits, banks 0x20, 0x40 and 0x60 end up as 0x21, 0x41 and 0x61.
+                 */
+                self.bank1 = match val & 0x1F {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000..=0x5FFF => self.bank2 = val & 0x03,
+            0x6000..=0x7FFF => self.advanced_banking = val & 0x01 != 0,
+            _ => panic!("Invalid MBC1 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::numbered_rom;
+
+    #[test]
+    fn bank0_remap() {
+        let mut mbc = MBC1::new(numbered_rom(128), 0, false);
+
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+
+        /* 0x20 -> 0x21 */
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn advanced_banking_mode() {
+        let mut mbc = MBC1::new(numbered_rom(128), 0x8000, false);
+
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x40);
+        assert_eq!(mbc.read_rom(0x4000), 0x41);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        /* Simple banking mode always maps RAM bank 0. */
+        mbc.write_rom(0x6000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+    }
+
+    #[test]
+    fn ram_enable() {
+        let mut mbc = MBC1::new(numbered_rom(4), 0x2000, false);
+
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn small_rom_ignores_upper_bits() {
+        let mut mbc = MBC1::new(numbered_rom(4), 0, false);
+
+        mbc.write_rom(0x2000, 0x07);
+        assert_eq!(mbc.read_rom(0x4000), 3);
+    }
+
+    #[test]
+    fn multicart() {
+        let mut mbc = MBC1::new(numbered_rom(64), 0, true);
+
+        mbc.write_rom(0x2000, 0x12);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x12);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x10);
+    }
+}
@@ -0,0 +1,115 @@
+use std::mem;
+
+use crate::mbc::{banked_read, MBC};
+
+/* 512 half-bytes built into the controller. */
+const RAM_SIZE: usize = 0x200;
+
+pub struct MBC2 {
+    rom: Vec<u8>,
+    ram: [u8; RAM_SIZE],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl MBC2 {
+    pub fn new(rom: Vec<u8>) -> MBC2 {
+        MBC2 {
+            rom,
+            ram: [0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl MBC for MBC2 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid MBC2 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_enabled {
+            /* Only 9 address lines, the RAM echoes across the whole area. Upper nibbles aren't
+             * wired and read as 1s.
+             */
+            true => 0xF0 | self.ram[addr as usize & (RAM_SIZE - 1)],
+            false => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            /* Address bit 8 tells whether this is the RAM enable or the ROM bank register. */
+            0x0000..=0x3FFF if addr & 0x100 == 0 => self.ram_enabled = val & 0x0F == 0x0A,
+            0x0000..=0x3FFF => {
+                self.rom_bank = match val & 0x0F {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000..=0x7FFF => {}
+            _ => panic!("Invalid MBC2 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        let val = val & 0x0F;
+        mem::replace(&mut self.ram[addr as usize & (RAM_SIZE - 1)], val) != val
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        for (nibble, byte) in self.ram.iter_mut().zip(data) {
+            *nibble = byte & 0x0F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::{numbered_rom, ROM_BANK_SIZE};
+
+    #[test]
+    fn register_selected_by_address_bit_8() {
+        let mut mbc = MBC2::new(numbered_rom(16));
+
+        /* Bit 8 clear, this is RAM enable and the bank doesn't change. */
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2100, 0x0A);
+        assert_eq!(mbc.read_rom(0x4000), 0x0A);
+
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn half_byte_ram() {
+        let mut mbc = MBC2::new(vec![0; 2 * ROM_BANK_SIZE]);
+
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xF2);
+
+        /* Echoed every 512 bytes. */
+        assert_eq!(mbc.read_ram(0xA200), 0xF2);
+        assert_eq!(mbc.read_ram(0xBE00), 0xF2);
+    }
+}
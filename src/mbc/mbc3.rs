@@ -0,0 +1,195 @@
+use std::mem;
+
+use crate::mbc::rtc::Rtc;
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+    /* Enables both the RAM and the RTC registers. */
+    ram_enabled: bool,
+    rom_bank: u8,
+    /* 0x00-0x03 selects a RAM bank, 0x08-0x0C maps an RTC register at 0xA000-0xBFFF instead. */
+    ram_bank: u8,
+}
+
+impl MBC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> MBC3 {
+        MBC3 {
+            rom,
+            ram: vec![0; ram_size],
+            rtc: match has_rtc {
+                true => Some(Rtc::new()),
+                false => None,
+            },
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        banked_ram_offset(self.ram.len(), self.ram_bank as usize, addr)
+    }
+}
+
+impl MBC for MBC3 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid MBC3 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match (self.ram_bank, &self.rtc) {
+            (0x00..=0x03, _) => match self.ram_offset(addr) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+            (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = val & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                /* Unlike MBC1 the whole 7 bits are checked, bank 0 is the only one remapped. */
+                self.rom_bank = match val & 0x7F {
+                    0 => 1,
+                    n => n,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = val,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(val);
+                }
+            }
+            _ => panic!("Invalid MBC3 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        match self.ram_bank {
+            0x00..=0x03 => match self.ram_offset(addr) {
+                Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+                None => false,
+            },
+            0x08..=0x0C => match &mut self.rtc {
+                Some(rtc) => {
+                    rtc.write(self.ram_bank, val);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+
+        if let Some(rtc) = &self.rtc {
+            data.extend(rtc.save());
+        }
+
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load(&data[len..]);
+        }
+    }
+
+    fn do_cycle(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.do_cycle();
+        }
+    }
+
+    fn sync_rtc_to_host(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.sync_to_host();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::rtc::RTC_SAVE_SIZE;
+    use crate::mbc::{numbered_rom, ROM_BANK_SIZE};
+
+    #[test]
+    fn rom_banking() {
+        let mut mbc = MBC3::new(numbered_rom(128), 0, false);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        /* No MBC1 like remapping of 0x20/0x40/0x60. */
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(0x7FFF), 0x7F);
+    }
+
+    #[test]
+    fn rtc_registers_mapping() {
+        let mut mbc = MBC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x8000, true);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_ram(0xA000, 0x42);
+
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 30);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 30);
+
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn save_data_layout() {
+        let mut mbc = MBC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000, true);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA001, 0x42);
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 5);
+
+        let data = mbc.save_data();
+        assert_eq!(data.len(), 0x2000 + RTC_SAVE_SIZE);
+        assert_eq!(data[1], 0x42);
+        /* Hours register. */
+        assert_eq!(data[0x2000 + 2 * 4], 5);
+
+        let mut loaded = MBC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000, true);
+        loaded.load_save_data(&data);
+        assert_eq!(loaded.save_data()[..0x2000 + 40], data[..0x2000 + 40]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::mem;
+
+use crate::mbc::{banked_ram_offset, banked_read, MBC};
+
+/* On rumble cartridges, bit 3 of the RAM bank register drives the motor. */
+const RUMBLE_MOTOR: u8 = 0x08;
+
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    /* 9 bits, unlike older controllers bank 0 can be mapped at 0x4000. */
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> MBC5 {
+        MBC5 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled {
+            return None;
+        }
+
+        banked_ram_offset(self.ram.len(), self.ram_bank as usize, addr)
+    }
+}
+
+impl MBC for MBC5 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid MBC5 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            /* MBC5 wants exactly 0x0A, not just the lower nibble. */
+            0x0000..=0x1FFF => self.ram_enabled = val == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | val as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((val & 0x01) as u16) << 8,
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = val & RUMBLE_MOTOR != 0;
+                    self.ram_bank = val & 0x07;
+                } else {
+                    self.ram_bank = val & 0x0F;
+                }
+            }
+            0x6000..=0x7FFF => {}
+            _ => panic!("Invalid MBC5 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn is_rumbling(&self) -> bool {
+        self.rumble
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::{numbered_rom, ROM_BANK_SIZE};
+
+    #[test]
+    fn nine_bits_rom_bank() {
+        let mut mbc = MBC5::new(numbered_rom(512), 0, false);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        assert_eq!(mbc.rom_bank, 0x105);
+    }
+
+    #[test]
+    fn rumble_motor() {
+        let mut mbc = MBC5::new(vec![0; 2 * ROM_BANK_SIZE], 0x8000, true);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x09);
+        assert!(mbc.is_rumbling());
+
+        /* The motor bit isn't part of the RAM bank. */
+        mbc.write_ram(0xA000, 0x42);
+        mbc.write_rom(0x4000, 0x01);
+        assert!(!mbc.is_rumbling());
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::mbc::eeprom::{Eeprom, EEPROM_SIZE};
+use crate::mbc::{banked_read, MBC};
+
+/* Accelerometer value when flat, and how much 1g of tilt changes it. */
+const ACCELEROMETER_CENTER: u16 = 0x81D0;
+const ACCELEROMETER_PER_G: f32 = 0x70 as f32;
+/* Latched values until the accelerometer gets sampled. */
+const ACCELEROMETER_ERASED: u16 = 0x8000;
+
+const ERASE_LATCH: u8 = 0x55;
+const LATCH: u8 = 0xAA;
+
+/* MBC7, with a 2 axis accelerometer and a 93LC56 EEPROM instead of RAM. Both are mapped at
+ * 0xA000-0xAFFF, address bits 4-7 selecting the register.
+ */
+pub struct MBC7 {
+    rom: Vec<u8>,
+    eeprom: Eeprom,
+    /* Needs both 0x0A written to 0x0000-0x1FFF and 0x40 to 0x4000-0x5FFF. */
+    ram_enabled: bool,
+    ram_enabled2: bool,
+    rom_bank: u8,
+
+    /* Current tilt in g, positive x to the right and positive y towards the player. */
+    tilt: (f32, f32),
+    latched: (u16, u16),
+    latch_erased: bool,
+}
+
+impl MBC7 {
+    pub fn new(rom: Vec<u8>) -> MBC7 {
+        MBC7 {
+            rom,
+            eeprom: Eeprom::new(),
+            ram_enabled: false,
+            ram_enabled2: false,
+            rom_bank: 1,
+
+            tilt: (0.0, 0.0),
+            latched: (ACCELEROMETER_ERASED, ACCELEROMETER_ERASED),
+            latch_erased: false,
+        }
+    }
+
+    fn is_ram_enabled(&self) -> bool {
+        self.ram_enabled && self.ram_enabled2
+    }
+
+    /* Tilting to the right lowers X, tilting towards the player raises Y. */
+    fn sample_accelerometer(&self) -> (u16, u16) {
+        let axis = |tilt: f32| (ACCELEROMETER_CENTER as f32 + tilt * ACCELEROMETER_PER_G) as u16;
+
+        (axis(-self.tilt.0), axis(self.tilt.1))
+    }
+}
+
+impl MBC for MBC7 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, 0, addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank as usize, addr),
+            _ => panic!("Invalid MBC7 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.is_ram_enabled() || addr >= 0xB000 {
+            return 0xFF;
+        }
+
+        match (addr >> 4) & 0x0F {
+            0x2 => self.latched.0 as u8,
+            0x3 => (self.latched.0 >> 8) as u8,
+            0x4 => self.latched.1 as u8,
+            0x5 => (self.latched.1 >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = val == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled2 = val == 0x40,
+            0x6000..=0x7FFF => {}
+            _ => panic!("Invalid MBC7 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        if !self.is_ram_enabled() || addr >= 0xB000 {
+            return false;
+        }
+
+        match ((addr >> 4) & 0x0F, val) {
+            (0x0, ERASE_LATCH) => {
+                self.latched = (ACCELEROMETER_ERASED, ACCELEROMETER_ERASED);
+                self.latch_erased = true;
+            }
+            (0x1, LATCH) if self.latch_erased => {
+                self.latched = self.sample_accelerometer();
+                self.latch_erased = false;
+            }
+            (0x8, _) => return self.eeprom.write(val),
+            _ => {}
+        }
+
+        false
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.eeprom.save()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.eeprom.load(&data[..EEPROM_SIZE.min(data.len())]);
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::ROM_BANK_SIZE;
+
+    fn read_axis(mbc: &MBC7, low: u16) -> u16 {
+        mbc.read_ram(low) as u16 | (mbc.read_ram(low + 0x10) as u16) << 8
+    }
+
+    #[test]
+    fn accelerometer_latch() {
+        let mut mbc = MBC7::new(vec![0; 2 * ROM_BANK_SIZE]);
+
+        assert_eq!(mbc.read_ram(0xA020), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc.set_tilt(1.0, -0.5);
+
+        /* Latching only works right after erasing. */
+        mbc.write_ram(0xA010, LATCH);
+        assert_eq!(read_axis(&mbc, 0xA020), ACCELEROMETER_ERASED);
+
+        mbc.write_ram(0xA000, ERASE_LATCH);
+        mbc.write_ram(0xA010, LATCH);
+        assert_eq!(read_axis(&mbc, 0xA020), ACCELEROMETER_CENTER - 0x70);
+        assert_eq!(read_axis(&mbc, 0xA040), ACCELEROMETER_CENTER - 0x38);
+
+        /* Stays latched. */
+        mbc.set_tilt(0.0, 0.0);
+        assert_eq!(read_axis(&mbc, 0xA020), ACCELEROMETER_CENTER - 0x70);
+    }
+}
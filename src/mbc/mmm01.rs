@@ -0,0 +1,241 @@
+use std::mem;
+
+use crate::mbc::{banked_ram_offset, banked_read, MBC, ROM_BANK_SIZE};
+
+/* The menu is the last 32KB of the ROM, mapped until the game is picked. */
+const MENU_SIZE: usize = 0x8000;
+
+/* Bit of the 0x0000-0x1FFF register locking the mapping of the selected game. */
+const MAP_ENABLE: u8 = 0x40;
+
+/* MMM01 multicart controller. The cartridge boots on its menu, which configures which part of the
+ * ROM and RAM the game sees and then locks it, the game then sees a MBC1 limited to its own banks.
+ * The multiplex bit of the mode register isn't emulated.
+ */
+pub struct MMM01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    /* Set once the menu locked the mapping. */
+    mapped: bool,
+
+    /* 5 bits, MBC1 like, extended by 2 middle and 2 high bits only writable by the menu. */
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    /* Bits 1-4 of rom_bank_low the game can't change. */
+    rom_bank_mask: u8,
+
+    /* 2 bits a game can change, extended by 2 high bits only writable by the menu. */
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    /* Bits of ram_bank_low the game can't change. */
+    ram_bank_mask: u8,
+
+    advanced_banking: bool,
+    /* Prevents the game from changing the MBC1 banking mode. */
+    mode_locked: bool,
+}
+
+impl MMM01 {
+    pub fn new(mut rom: Vec<u8>, ram_size: usize) -> MMM01 {
+        /* Some dumps store the menu first, put it back where the controller expects it. */
+        if rom.len() > MENU_SIZE && is_mmm01_header(&rom, 0) && !is_mmm01_header(&rom, rom.len() - MENU_SIZE) {
+            rom.rotate_left(MENU_SIZE);
+        }
+
+        MMM01 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            mapped: false,
+
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_mask: 0,
+
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_bank_mask: 0,
+
+            advanced_banking: false,
+            mode_locked: false,
+        }
+    }
+
+    /* Bits of rom_bank_low left to the game once mapped. */
+    fn rom_bank_unmasked(&self) -> u8 {
+        0x1F & !self.rom_bank_mask
+    }
+
+    fn rom_bank_base(&self) -> usize {
+        ((self.rom_bank_high as usize) << 7) | ((self.rom_bank_mid as usize) << 5)
+    }
+
+    fn rom_bank_0000(&self) -> usize {
+        match self.mapped {
+            true => self.rom_bank_base() | (self.rom_bank_low & !self.rom_bank_unmasked()) as usize,
+            false => (self.rom.len() / ROM_BANK_SIZE).saturating_sub(2),
+        }
+    }
+
+    fn rom_bank_4000(&self) -> usize {
+        if !self.mapped {
+            return (self.rom.len() / ROM_BANK_SIZE).saturating_sub(1);
+        }
+
+        /* Like on MBC1 the game can't map its bank 0 there, that's checked on its own bits only. */
+        let low = match self.rom_bank_low & self.rom_bank_unmasked() {
+            0 => self.rom_bank_low | 0x01,
+            _ => self.rom_bank_low,
+        };
+
+        self.rom_bank_base() | low as usize
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled {
+            return None;
+        }
+
+        /* In MBC1 simple banking mode, the game always sees its first RAM bank. */
+        let low = match self.advanced_banking {
+            true => self.ram_bank_low,
+            false => self.ram_bank_low & self.ram_bank_mask,
+        };
+        let bank = (self.ram_bank_high << 2 | low) as usize;
+
+        banked_ram_offset(self.ram.len(), bank, addr)
+    }
+
+    /* Once mapped, the game only changes the bits the menu left to it. */
+    fn write_masked(&self, old: u8, new: u8, writable: u8) -> u8 {
+        match self.mapped {
+            true => (old & !writable) | (new & writable),
+            false => new,
+        }
+    }
+}
+
+/* Whether the header at the given offset is a MMM01 one. */
+fn is_mmm01_header(rom: &[u8], offset: usize) -> bool {
+    matches!(rom.get(offset + 0x147), Some(0x0B..=0x0D))
+}
+
+impl MBC for MMM01 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => banked_read(&self.rom, self.rom_bank_0000(), addr),
+            0x4000..=0x7FFF => banked_read(&self.rom, self.rom_bank_4000(), addr),
+            _ => panic!("Invalid MMM01 ROM address {:04X}", addr),
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = val & 0x0F == 0x0A;
+
+                if !self.mapped {
+                    self.ram_bank_mask = (val >> 4) & 0x03;
+                    self.mapped = val & MAP_ENABLE != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                self.rom_bank_low = self.write_masked(self.rom_bank_low, val & 0x1F, self.rom_bank_unmasked());
+
+                if !self.mapped {
+                    self.rom_bank_mid = (val >> 5) & 0x03;
+                }
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank_low = self.write_masked(self.ram_bank_low, val & 0x03, !self.ram_bank_mask & 0x03);
+
+                if !self.mapped {
+                    self.ram_bank_high = (val >> 2) & 0x03;
+                    self.rom_bank_high = (val >> 4) & 0x03;
+                    self.mode_locked = val & 0x40 != 0;
+                }
+            }
+            0x6000..=0x7FFF => {
+                if !self.mapped || !self.mode_locked {
+                    self.advanced_banking = val & 0x01 != 0;
+                }
+
+                if !self.mapped {
+                    self.rom_bank_mask = ((val >> 2) & 0x0F) << 1;
+                }
+            }
+            _ => panic!("Invalid MMM01 register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool {
+        match self.ram_offset(addr) {
+            Some(offset) => mem::replace(&mut self.ram[offset], val) != val,
+            None => false,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::numbered_rom;
+
+    #[test]
+    fn menu_then_game() {
+        let mut mbc = MMM01::new(numbered_rom(64), 0);
+
+        /* The menu is in the last 32KB. */
+        assert_eq!(mbc.read_rom(0x0000), 62);
+        assert_eq!(mbc.read_rom(0x4000), 63);
+
+        /* Map the 128KB game starting at bank 0x20: bank bits 3-4 are fixed. */
+        mbc.write_rom(0x2000, 0x20);
+        mbc.write_rom(0x6000, 0b1100 << 2);
+        mbc.write_rom(0x0000, MAP_ENABLE);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 0x25);
+
+        /* The game can't get out of its banks, nor unlock the mapping. */
+        mbc.write_rom(0x2000, 0x1F);
+        assert_eq!(mbc.read_rom(0x4000), 0x27);
+        mbc.write_rom(0x4000, 0x30);
+        mbc.write_rom(0x0000, 0x00);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+    }
+
+    #[test]
+    fn menu_first_dump() {
+        let mut rom = numbered_rom(8);
+        rom[0x147] = 0x0B;
+
+        /* The menu bank ends up last. */
+        let mbc = MMM01::new(rom, 0);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+}
@@ -1,51 +1,336 @@
-use std::path;
-use std::fs;
+use std::fmt;
 
+use crate::camera::Camera;
+use crate::header::{CartridgeError, CartridgeHeader, CartridgeType, LOGO_SIZE, LOGO_START};
+use crate::infrared::Infrared;
+
+mod camera;
+mod eeprom;
+mod huc1;
+mod huc3;
 mod mbc0;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc7;
+mod mmm01;
+mod rtc;
+mod wisdom_tree;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/* Reads from a 16KB ROM bank. Bank numbers past the end of the ROM wrap around, the unused upper
+ * bank bits aren't wired to it.
+ */
+fn banked_read(rom: &[u8], bank: usize, addr: u16) -> u8 {
+    let bank = bank % (rom.len() / ROM_BANK_SIZE).max(1);
+
+    match rom.get(bank * ROM_BANK_SIZE + (addr as usize % ROM_BANK_SIZE)) {
+        Some(byte) => *byte,
+        None => 0xFF,
+    }
+}
+
+/* Offset in the RAM of a 0xA000-0xBFFF address in a 8KB RAM bank, None when there is no RAM.
+ * RAM chips smaller than a bank (2KB) are mirrored, bigger bank numbers wrap.
+ */
+fn banked_ram_offset(ram_len: usize, bank: usize, addr: u16) -> Option<usize> {
+    match ram_len {
+        0 => None,
+        len => Some((bank * RAM_BANK_SIZE + (addr as usize - 0xA000)) % len),
+    }
+}
+
+/* Every ROM bank is filled with its own number. */
+#[cfg(test)]
+fn numbered_rom(banks: usize) -> Vec<u8> {
+    (0..banks).flat_map(|bank| vec![bank as u8; ROM_BANK_SIZE]).collect()
+}
 
 pub trait MBC {
     fn read_rom(&self, addr: u16) -> u8;
     fn read_ram(&self, addr: u16) -> u8;
 
     fn write_rom(&mut self, addr: u16, val: u8);
-    fn write_ram(&mut self, addr: u16, val: u8);
+    /* Returns whether the write changed the battery backed state, for the frontend to know when
+     * the save file is out of date.
+     */
+    fn write_ram(&mut self, addr: u16, val: u8) -> bool;
+
+    /* Called every cycle, for controllers with their own clock. */
+    fn do_cycle(&mut self) {}
+
+    /* Catches the RTC, if any, up with the host clock. */
+    fn sync_rtc_to_host(&mut self) {}
+
+    /* Battery backed state as stored in save files: the RAM, followed by the RTC state for
+     * controllers that have one.
+     */
+    fn save_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
 
-    fn rom_name(&self) -> String {
-        const TITLE_START: u16 = 0x134;
-        const CGB_FLAG: u16 = 0x143;
+    fn load_save_data(&mut self, _data: &[u8]) {}
 
-        let title_size = match self.read_rom(CGB_FLAG) {
-            0x80 | 0xC0 => 11,
-            _ => 16,
+    /* State of the rumble motor, for cartridges that have one. */
+    fn is_rumbling(&self) -> bool {
+        false
+    }
+
+    fn infrared(&mut self) -> Option<&mut Infrared> {
+        None
+    }
+
+    /* Tilt in g for cartridges with an accelerometer, positive x to the right and positive y
+     * towards the player.
+     */
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        None
+    }
+}
+
+/* Controller emulated for a cartridge. Usually the one the header gives, but multicarts don't
+ * always tell.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapper {
+    MBC0,
+    MBC1,
+    /* MBC1 wired for a multicart of 256KB games. */
+    MBC1M,
+    MBC2,
+    MBC3,
+    MBC5,
+    MBC7,
+    MMM01,
+    HuC1,
+    HuC3,
+    PocketCamera,
+    WisdomTree,
+}
+
+impl fmt::Display for Mapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Mapper::MBC0 => "None",
+            Mapper::MBC1 => "MBC1",
+            Mapper::MBC1M => "MBC1 multicart (MBC1M)",
+            Mapper::MBC2 => "MBC2",
+            Mapper::MBC3 => "MBC3",
+            Mapper::MBC5 => "MBC5",
+            Mapper::MBC7 => "MBC7",
+            Mapper::MMM01 => "MMM01 multicart",
+            Mapper::HuC1 => "HuC1",
+            Mapper::HuC3 => "HuC3",
+            Mapper::PocketCamera => "Pocket Camera",
+            Mapper::WisdomTree => "Wisdom Tree (32KB banks multicart)",
         };
 
-        let mut title = String::with_capacity(title_size as usize);
+        write!(f, "{}", name)
+    }
+}
 
-        for i in 0..title_size {
-            match self.read_rom(TITLE_START + i) {
-                0 => break,
-                c => title.push(c as char),
-            }
+/* Picks the controller from the header, checking the ROM for the multicarts lying about it:
+ * MBC1M carts claim to be a plain MBC1, and Wisdom Tree games and bootleg multicarts of 32KB games
+ * claim to be a single 32KB game.
+ */
+pub fn detect_mapper(header: &CartridgeHeader, rom: &[u8]) -> Result<Mapper, CartridgeError> {
+    Ok(match header.cartridge_type {
+        CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery if is_wisdom_tree(rom) => {
+            Mapper::WisdomTree
         }
+        CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery => Mapper::MBC0,
+        CartridgeType::MBC1 | CartridgeType::MBC1Ram | CartridgeType::MBC1RamBattery if is_mbc1_multicart(rom) => {
+            Mapper::MBC1M
+        }
+        CartridgeType::MBC1 | CartridgeType::MBC1Ram | CartridgeType::MBC1RamBattery => Mapper::MBC1,
+        CartridgeType::MBC2 | CartridgeType::MBC2Battery => Mapper::MBC2,
+        CartridgeType::MMM01 | CartridgeType::MMM01Ram | CartridgeType::MMM01RamBattery => Mapper::MMM01,
+        CartridgeType::MBC3TimerBattery
+        | CartridgeType::MBC3TimerRamBattery
+        | CartridgeType::MBC3
+        | CartridgeType::MBC3Ram
+        | CartridgeType::MBC3RamBattery => Mapper::MBC3,
+        CartridgeType::MBC5
+        | CartridgeType::MBC5Ram
+        | CartridgeType::MBC5RamBattery
+        | CartridgeType::MBC5Rumble
+        | CartridgeType::MBC5RumbleRam
+        | CartridgeType::MBC5RumbleRamBattery => Mapper::MBC5,
+        CartridgeType::MBC7SensorRumbleRamBattery => Mapper::MBC7,
+        CartridgeType::PocketCamera => Mapper::PocketCamera,
+        CartridgeType::HuC1RamBattery => Mapper::HuC1,
+        CartridgeType::HuC3 => Mapper::HuC3,
+        /* Whatever the controller is, there are no banks to switch. Bigger ROMs would need a guess,
+         * and a wrong one only makes for a harder to understand crash.
+         */
+        CartridgeType::Unknown(_) if rom.len() <= 0x8000 => Mapper::MBC0,
+        cartridge_type => return Err(CartridgeError::UnsupportedType(cartridge_type)),
+    })
+}
+
+/* A bigger ROM than the 32KB it claims is most often just an overdump, so look for a second sign:
+ * Wisdom Tree games have the publisher name in them, and bootleg multicarts have the header of
+ * their second game right after the first 32KB.
+ */
+fn is_wisdom_tree(rom: &[u8]) -> bool {
+    const SECOND_GAME: usize = 0x8000;
+    const PUBLISHER_NAMES: [&[u8]; 2] = [b"WISDOM TREE", b"WISDOM\0TREE"];
 
-        title
+    if rom.len() <= SECOND_GAME {
+        return false;
     }
+
+    let has_publisher_name = PUBLISHER_NAMES
+        .iter()
+        .any(|name| rom.windows(name.len()).any(|window| window == *name));
+    let has_second_game = rom.get(SECOND_GAME + LOGO_START..SECOND_GAME + LOGO_START + LOGO_SIZE)
+        == Some(&rom[LOGO_START..LOGO_START + LOGO_SIZE]);
+
+    has_publisher_name || has_second_game
+}
+
+/* MBC1M carts are 1MB and have a game with its own header every 256KB, so look for a second
+ * Nintendo logo at the start of bank 0x10.
+ */
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const MULTICART_ROM_SIZE: usize = 0x10_0000;
+    const SECOND_GAME: usize = 0x4_0000;
+
+    rom.len() == MULTICART_ROM_SIZE
+        && rom[LOGO_START..LOGO_START + LOGO_SIZE] == rom[SECOND_GAME + LOGO_START..SECOND_GAME + LOGO_START + LOGO_SIZE]
 }
 
-pub fn load_cartridge(rom_path: &path::Path) -> Box<MBC> {
-    const CARTRIDGE_TYPE_IDX: usize  = 0x147;
+pub fn load_cartridge(header: &CartridgeHeader, mapper: Mapper, cartridge_data: Vec<u8>) -> Box<dyn MBC> {
+    let ram_size = header.ram_size;
+
+    match mapper {
+        Mapper::MBC0 => Box::new(mbc0::MBC0::new(cartridge_data, ram_size)),
+        Mapper::MBC1 => Box::new(mbc1::MBC1::new(cartridge_data, ram_size, false)),
+        Mapper::MBC1M => Box::new(mbc1::MBC1::new(cartridge_data, ram_size, true)),
+        Mapper::MBC2 => Box::new(mbc2::MBC2::new(cartridge_data)),
+        Mapper::MBC3 => {
+            let has_rtc = header.cartridge_type.has_rtc();
+            Box::new(mbc3::MBC3::new(cartridge_data, ram_size, has_rtc))
+        }
+        Mapper::MBC5 => {
+            let has_rumble = header.cartridge_type.has_rumble();
+            Box::new(mbc5::MBC5::new(cartridge_data, ram_size, has_rumble))
+        }
+        Mapper::MBC7 => Box::new(mbc7::MBC7::new(cartridge_data)),
+        Mapper::MMM01 => Box::new(mmm01::MMM01::new(cartridge_data, ram_size)),
+        Mapper::PocketCamera => Box::new(camera::PocketCamera::new(cartridge_data, ram_size)),
+        Mapper::HuC1 => Box::new(huc1::HuC1::new(cartridge_data, ram_size)),
+        Mapper::HuC3 => Box::new(huc3::HuC3::new(cartridge_data, ram_size)),
+        Mapper::WisdomTree => Box::new(wisdom_tree::WisdomTree::new(cartridge_data)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cartridge(cartridge_type: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = cartridge_type;
+        rom[0x149] = ram_size;
+        rom
+    }
+
+    fn load(cartridge_data: Vec<u8>) -> Box<dyn MBC> {
+        let header = CartridgeHeader::parse(&cartridge_data).unwrap();
+        let mapper = detect_mapper(&header, &cartridge_data).unwrap();
+        load_cartridge(&header, mapper, cartridge_data)
+    }
+
+    #[test]
+    fn ram_sized_from_header() {
+        let mut mbc = load(cartridge(0x09, 0x02));
+        mbc.write_ram(0xBFFF, 0x42);
+        assert_eq!(mbc.read_ram(0xBFFF), 0x42);
+
+        let mut mbc = load(cartridge(0x00, 0x00));
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn small_ram_is_mirrored() {
+        let mut mbc = load(cartridge(0x08, 0x01));
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA800), 0x42);
+    }
 
-    let cartridge_data = fs::read(rom_path).unwrap();
+    #[test]
+    fn unsupported_type() {
+        let cartridge_data = cartridge(0x20, 0x00);
+        let header = CartridgeHeader::parse(&cartridge_data).unwrap();
 
-    if cartridge_data.len() < 0x150 {
-        eprintln!("ROM too small, can't even fit header ({} bytes)", cartridge_data.len());
-    } else {
-        println!("Loaded cartridge of size {} bytes\n--------------\n", cartridge_data.len());
+        assert_eq!(
+            detect_mapper(&header, &cartridge_data),
+            Err(CartridgeError::UnsupportedType(CartridgeType::MBC6))
+        );
     }
 
+    #[test]
+    fn unknown_type() {
+        let mut cartridge_data = cartridge(0xC7, 0x00);
+        cartridge_data[0x4000] = 0x42;
+        assert_eq!(load(cartridge_data.clone()).read_rom(0x4000), 0x42);
+
+        cartridge_data.resize(0x10000, 0);
+        let header = CartridgeHeader::parse(&cartridge_data).unwrap();
+
+        assert_eq!(
+            detect_mapper(&header, &cartridge_data),
+            Err(CartridgeError::UnsupportedType(CartridgeType::Unknown(0xC7)))
+        );
+    }
+
+    fn detect(cartridge_data: &[u8]) -> Mapper {
+        let header = CartridgeHeader::parse(cartridge_data).unwrap();
+        detect_mapper(&header, cartridge_data).unwrap()
+    }
+
+    #[test]
+    fn multicarts() {
+        let logo = [0xCE; LOGO_SIZE];
+
+        /* Bigger than the 32KB it claims, but nothing else tells it's a multicart. */
+        let mut rom = cartridge(0x00, 0x00);
+        rom[LOGO_START..LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        rom.resize(0x40000, 0);
+        assert_eq!(detect(&rom), Mapper::MBC0);
+
+        rom[0x8000 + LOGO_START..0x8000 + LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        assert_eq!(detect(&rom), Mapper::WisdomTree);
+
+        let mut rom = cartridge(0x00, 0x00);
+        rom[LOGO_START..LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        rom.resize(0x20000, 0);
+        assert_eq!(detect(&rom), Mapper::MBC0);
+        rom[0x1_2345..0x1_2345 + 11].copy_from_slice(b"WISDOM TREE");
+        assert_eq!(detect(&rom), Mapper::WisdomTree);
+
+        let mut rom = cartridge(0x01, 0x00);
+        rom.resize(0x10_0000, 0);
+        rom[LOGO_START..LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        assert_eq!(detect(&rom), Mapper::MBC1);
+        rom[0x4_0000 + LOGO_START..0x4_0000 + LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        assert_eq!(detect(&rom), Mapper::MBC1M);
 
-    match cartridge_data[CARTRIDGE_TYPE_IDX] {
-        0x00 => Box::new(mbc0::MBC0::new(cartridge_data)),
-        n => panic!("Unimplemented cartridge type {}", n),
+        /* The first game claims to be MBC1, the menu at the end has the MMM01 header. */
+        let menu = rom.len() - 0x8000;
+        rom[menu + LOGO_START..menu + LOGO_START + LOGO_SIZE].copy_from_slice(&logo);
+        rom[menu + 0x147] = 0x0D;
+        rom[menu + 0x149] = 0x03;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.cartridge_type, CartridgeType::MMM01RamBattery);
+        assert_eq!(header.ram_size, 0x8000);
+        assert_eq!(detect(&rom), Mapper::MMM01);
     }
 }
@@ -0,0 +1,336 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::CYCLES_PER_SECOND;
+
+/* Bits of the DH register. */
+const DAY_HIGH: u8 = 0x01;
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/* The day counter is 9 bits wide. */
+const DAY_COUNTER_WRAP: u64 = 512;
+
+/* Size of the RTC state appended to the RAM in save files: the 5 registers then their latched
+ * copy, each as a little endian u32, followed by a u64 unix timestamp. Some emulators only write
+ * a u32 timestamp.
+ */
+pub const RTC_SAVE_SIZE: usize = 48;
+const RTC_SAVE_SIZE_SHORT_TIMESTAMP: usize = 44;
+
+/* Real Time Clock found on some MBC3 cartridges, counting seconds, minutes, hours and up to 511
+ * days. The game only ever reads a latched copy of the registers.
+ */
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+
+    /* Copy of the registers, in 0x08-0x0C order, taken on a 0x00 then 0x01 write to 0x6000-0x7FFF. */
+    latched: [u8; 5],
+    last_latch_write: u8,
+
+    /* Cycles elapsed in the current second. */
+    cycles: usize,
+    /* Unix time at which the registers were last synced with the host clock. */
+    timestamp: u64,
+}
+
+impl Rtc {
+    pub fn new() -> Rtc {
+        Rtc {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+
+            latched: [0; 5],
+            last_latch_write: 0xFF,
+
+            cycles: 0,
+            timestamp: Rtc::now(),
+        }
+    }
+
+    /* Reads the latched value of a 0x08-0x0C register. */
+    pub fn read(&self, reg: u8) -> u8 {
+        self.latched[(reg - 0x08) as usize]
+    }
+
+    /* Writes go straight to the counting registers. */
+    pub fn write(&mut self, reg: u8, val: u8) {
+        match reg {
+            0x08 => {
+                self.seconds = val & 0x3F;
+                /* Writing the seconds resets the sub-second divider. */
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = val & 0x3F,
+            0x0A => self.hours = val & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | val as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((val & DAY_HIGH) as u16) << 8;
+                self.halted = val & HALT != 0;
+                self.day_carry = val & DAY_CARRY != 0;
+            }
+            _ => panic!("Invalid RTC register {:02X}", reg),
+        }
+    }
+
+    pub fn write_latch(&mut self, val: u8) {
+        if self.last_latch_write == 0x00 && val == 0x01 {
+            self.latched = self.registers();
+        }
+
+        self.last_latch_write = val;
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+
+        for reg in self.registers().iter().chain(self.latched.iter()) {
+            data.extend_from_slice(&(*reg as u32).to_le_bytes());
+        }
+
+        data.extend_from_slice(&Rtc::now().to_le_bytes());
+
+        data
+    }
+
+    /* Ignores anything that doesn't look like an RTC footer. */
+    pub fn load(&mut self, data: &[u8]) {
+        if data.len() != RTC_SAVE_SIZE && data.len() != RTC_SAVE_SIZE_SHORT_TIMESTAMP {
+            return;
+        }
+
+        let regs: Vec<u8> = data[..40].chunks(4).map(|reg| reg[0]).collect();
+
+        for (reg, &val) in (0x08..=0x0C).zip(regs[..5].iter()) {
+            self.write(reg, val);
+        }
+        self.latched.copy_from_slice(&regs[5..]);
+
+        let mut timestamp = [0; 8];
+        timestamp[..data.len() - 40].copy_from_slice(&data[40..]);
+        self.timestamp = u64::from_le_bytes(timestamp);
+    }
+
+    pub fn do_cycle(&mut self) {
+        if self.halted {
+            return;
+        }
+
+        self.cycles += 1;
+
+        if self.cycles == CYCLES_PER_SECOND {
+            self.cycles = 0;
+            self.tick();
+        }
+    }
+
+    /* Advances the clock by the host time elapsed since the last sync, for instance to account for
+     * the time the emulator wasn't running between two sessions.
+     */
+    pub fn sync_to_host(&mut self) {
+        let now = Rtc::now();
+
+        if !self.halted {
+            self.advance(now.saturating_sub(self.timestamp));
+        }
+
+        self.timestamp = now;
+    }
+
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halted {
+            return;
+        }
+
+        /* Out of range values written by the game count up to the register width before wrapping,
+         * so step through those one second at a time.
+         */
+        while seconds > 0 && !(self.seconds < 60 && self.minutes < 60 && self.hours < 24) {
+            self.tick();
+            seconds -= 1;
+        }
+
+        let total = self.days as u64 * SECONDS_PER_DAY
+            + self.hours as u64 * 3600
+            + self.minutes as u64 * 60
+            + self.seconds as u64
+            + seconds;
+        let days = total / SECONDS_PER_DAY;
+
+        if days >= DAY_COUNTER_WRAP {
+            self.day_carry = true;
+        }
+
+        self.days = (days % DAY_COUNTER_WRAP) as u16;
+        self.hours = (total % SECONDS_PER_DAY / 3600) as u8;
+        self.minutes = (total % 3600 / 60) as u8;
+        self.seconds = (total % 60) as u8;
+    }
+
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days += 1;
+        if self.days as u64 == DAY_COUNTER_WRAP {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        let mut dh = (self.days >> 8) as u8 & DAY_HIGH;
+
+        if self.halted {
+            dh |= HALT;
+        }
+
+        if self.day_carry {
+            dh |= DAY_CARRY;
+        }
+
+        [self.seconds, self.minutes, self.hours, self.days as u8, dh]
+    }
+
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+
+        [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|reg| rtc.read(reg))
+    }
+
+    #[test]
+    fn latch_on_rising_write() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x08, 10);
+        assert_eq!(rtc.read(0x08), 0);
+
+        /* Only 0x00 then 0x01 latches. */
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 0);
+
+        assert_eq!(latched(&mut rtc)[0], 10);
+
+        rtc.write(0x08, 20);
+        assert_eq!(rtc.read(0x08), 10);
+    }
+
+    #[test]
+    fn counts_emulated_seconds() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0A, 23);
+
+        for _ in 0..CYCLES_PER_SECOND {
+            rtc.do_cycle();
+        }
+
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x0C, HALT);
+        for _ in 0..CYCLES_PER_SECOND {
+            rtc.do_cycle();
+        }
+        rtc.advance(1000);
+
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, HALT]);
+    }
+
+    #[test]
+    fn day_carry() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, DAY_HIGH);
+        rtc.advance(SECONDS_PER_DAY + 61);
+
+        assert_eq!(latched(&mut rtc), [1, 1, 0, 0, DAY_CARRY]);
+
+        /* The carry bit stays set until the game clears it. */
+        rtc.advance(SECONDS_PER_DAY);
+        assert_eq!(latched(&mut rtc), [1, 1, 0, 1, DAY_CARRY]);
+
+        rtc.write(0x0C, 0);
+        assert_eq!(latched(&mut rtc)[4], 0);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x09, 42);
+        rtc.write(0x0C, DAY_HIGH | DAY_CARRY);
+        latched(&mut rtc);
+        rtc.write(0x08, 12);
+
+        let data = rtc.save();
+        assert_eq!(data.len(), RTC_SAVE_SIZE);
+
+        let mut loaded = Rtc::new();
+        loaded.load(&data);
+
+        assert_eq!(loaded.registers(), [12, 42, 0, 0, DAY_HIGH | DAY_CARRY]);
+        assert_eq!(loaded.read(0x08), 0);
+        assert_eq!(loaded.read(0x09), 42);
+        assert!(loaded.timestamp > 0);
+
+        /* Older 44 bytes footer, with a 32 bits timestamp. */
+        let mut loaded = Rtc::new();
+        loaded.load(&data[..RTC_SAVE_SIZE_SHORT_TIMESTAMP]);
+        assert_eq!(loaded.registers(), [12, 42, 0, 0, DAY_HIGH | DAY_CARRY]);
+    }
+
+    #[test]
+    fn out_of_range_seconds_wrap_without_carry() {
+        let mut rtc = Rtc::new();
+
+        rtc.write(0x08, 62);
+        rtc.advance(2);
+
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0]);
+    }
+}
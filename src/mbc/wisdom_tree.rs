@@ -0,0 +1,71 @@
+use crate::mbc::MBC;
+
+const GAME_BANK_SIZE: usize = 0x8000;
+
+/* Wisdom Tree mapper, also found on many bootleg multicarts of 32KB games. There is no RAM, and
+ * the whole 0x0000-0x7FFF area is switched at once by writing anything to 0x0000-0x3FFF, the low
+ * byte of the address giving the bank.
+ */
+pub struct WisdomTree {
+    rom: Vec<u8>,
+    rom_bank: u8,
+}
+
+impl WisdomTree {
+    pub fn new(rom: Vec<u8>) -> WisdomTree {
+        WisdomTree { rom, rom_bank: 0 }
+    }
+}
+
+impl MBC for WisdomTree {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = self.rom_bank as usize % (self.rom.len() / GAME_BANK_SIZE).max(1);
+
+        match self.rom.get(bank * GAME_BANK_SIZE + addr as usize) {
+            Some(byte) => *byte,
+            None => 0xFF,
+        }
+    }
+
+    fn read_ram(&self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_rom(&mut self, addr: u16, _val: u8) {
+        match addr {
+            0x0000..=0x3FFF => self.rom_bank = addr as u8,
+            0x4000..=0x7FFF => {}
+            _ => panic!("Invalid Wisdom Tree register address {:04X}", addr),
+        }
+    }
+
+    fn write_ram(&mut self, _addr: u16, _val: u8) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mbc::numbered_rom;
+
+    #[test]
+    fn bank_from_address() {
+        let mut mbc = WisdomTree::new(numbered_rom(16));
+
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        /* The value written doesn't matter, only the low byte of the address. */
+        mbc.write_rom(0x0103, 0x00);
+        assert_eq!(mbc.read_rom(0x0000), 6);
+        assert_eq!(mbc.read_rom(0x7FFF), 7);
+
+        /* Bank numbers past the end of the ROM wrap. */
+        mbc.write_rom(0x3F09, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 3);
+
+        mbc.write_rom(0x4002, 0x00);
+        assert_eq!(mbc.read_rom(0x0000), 2);
+    }
+}
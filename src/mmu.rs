@@ -1,11 +1,14 @@
-use std::path;
-
+use crate::interrupt::{self, Interrupt};
+use crate::framebuffer::Framebuffer;
+use crate::camera::Camera;
+use crate::infrared::Infrared;
 use crate::mbc;
 use crate::ppu;
 use crate::joypad;
+use crate::serial;
 use crate::timer;
 
-const DMG_ROM_SIZE: usize = 0x100;
+pub const DMG_ROM_SIZE: usize = 0x100;
 const DMG_ROM: [u8; DMG_ROM_SIZE] = [
     0x31, 0xfe, 0xff, 0xaf, 0x21, 0xff, 0x9f, 0x32, 0xcb, 0x7c, 0x20, 0xfb, 0x21, 0x26, 0xff, 0xe,
     0x11, 0x3e, 0x80, 0x32, 0xe2, 0xc, 0x3e, 0xf3, 0xe2, 0x32, 0x3e, 0x77, 0x77, 0x3e, 0xfc, 0xe0,
@@ -28,61 +31,77 @@ const DMG_ROM: [u8; DMG_ROM_SIZE] = [
 /* 8kB internal ram */
 const INTERNAL_RAM_SIZE: usize = 0x2000;
 const HIGH_RAM_SIZE: usize = 0x7f;
-const EMPTY_RAM_SZ: usize = 0x34;
 
 pub struct MMU {
-    mbc: Box<mbc::MBC>,
+    mbc: Box<dyn mbc::MBC>,
     ram: [u8; INTERNAL_RAM_SIZE],
     high_ram: [u8; HIGH_RAM_SIZE],
-    empty_ram: [u8; EMPTY_RAM_SZ],
     ppu: ppu::PPU,
     joypad: joypad::Joypad,
     timer: timer::Timer,
+    serial: serial::Serial,
+    /* Last value written to the OAM DMA register, the upper byte of the source address. */
+    oam_dma: u8,
 
     interrupt_enable: u8,
     interrupt_flag: u8,
+    boot_rom: Vec<u8>,
     dmg_disabled: bool,
+
+    /* Set when the cartridge RAM or clock changed since the last save. */
+    save_dirty: bool,
 }
 
 impl MMU {
-    pub fn new(path: &path::Path) -> MMU {
+    pub fn new(mbc: Box<dyn mbc::MBC>) -> MMU {
         MMU {
-            mbc: mbc::load_cartridge(path),
+            mbc,
             ram: [0; INTERNAL_RAM_SIZE],
             high_ram: [0; HIGH_RAM_SIZE],
-            empty_ram: [0; EMPTY_RAM_SZ],
 
             ppu: ppu::PPU::new(),
             joypad: joypad::Joypad::new(),
             timer: timer::Timer::new(),
+            serial: serial::Serial::new(),
+            oam_dma: 0xFF,
 
             interrupt_enable: 0,
             interrupt_flag: 0,
 
+            boot_rom: DMG_ROM.to_vec(),
             dmg_disabled: false,
+
+            save_dirty: false,
         }
     }
 
     pub fn do_cycle(&mut self) {
-        self.ppu.do_cycle();
-        self.timer.do_cycles();
+        self.interrupt_flag |= self.ppu.do_cycle();
+
+        if self.timer.do_cycles() {
+            self.request_interrupt(Interrupt::Timer);
+        }
 
-        // self.joypad.do_cycle(self.ppu.get_lcd_ref());
+        if self.serial.do_cycle() {
+            self.request_interrupt(Interrupt::Serial);
+        }
+
+        self.mbc.do_cycle();
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
-            0x0000...0x00FF if !self.dmg_disabled => DMG_ROM[addr as usize],
-            0x0000...0x7FFF => self.mbc.read_rom(addr),
-            0x8000...0x9FFF => self.ppu.read_vram(addr), /* 8KB Video RAM (VRAM) */
-            0xA000...0xBFFF => panic!("NOT IMPLEMENTED"), /* 8KB External RAM */
-            0xC000...0xDFFF => self.ram[(addr - 0xC000) as usize],   /* 8kB Internal RAM size */
-            0xE000...0xFDFF => self.read(addr- 0x2000), /* Same as C000-DDFF (ECHO) */
-            0xFE00...0xFE9F => self.ppu.read_oam(addr), /* Sprite Attribute Table (OAM) */
-            0xFEA0...0xFEFF => 0, /* Not Usable */
-            0xFF00...0xFF4B => self.read_io_port(addr),
-            0xFF4C...0xFF7F => self.empty_ram[(addr - 0xFF4C) as usize],
-            0xFF80...0xFFFE => self.high_ram[(addr - 0xFF80) as usize], /* High RAM (HRAM) */
+            0x0000..=0x00FF if !self.dmg_disabled => self.boot_rom[addr as usize],
+            0x0000..=0x7FFF => self.mbc.read_rom(addr),
+            0x8000..=0x9FFF => self.ppu.read_vram(addr), /* 8KB Video RAM (VRAM) */
+            0xA000..=0xBFFF => self.mbc.read_ram(addr), /* 8KB External RAM */
+            0xC000..=0xDFFF => self.ram[(addr - 0xC000) as usize],   /* 8kB Internal RAM size */
+            0xE000..=0xFDFF => self.read(addr- 0x2000), /* Same as C000-DDFF (ECHO) */
+            0xFE00..=0xFE9F => self.ppu.read_oam(addr), /* Sprite Attribute Table (OAM) */
+            0xFEA0..=0xFEFF => 0, /* Not Usable */
+            0xFF00..=0xFF4B => self.read_io_port(addr),
+            0xFF4C..=0xFF7F => 0xFF, /* Unmapped on the DMG, CGB registers like KEY1 read as 0xFF. */
+            0xFF80..=0xFFFE => self.high_ram[(addr - 0xFF80) as usize], /* High RAM (HRAM) */
             0xFFFF => self.interrupt_enable, /* Interrupt Enable Register */
         }
     }
@@ -90,11 +109,13 @@ impl MMU {
     pub fn read_io_port(&self, addr: u16) -> u8 {
         match addr {
             0xFF00 => self.joypad.read(),
-            0xFF01...0xFF02 => { eprintln!("Serial Data Transfer registers not implemented"); 0 },
-            0xFF04...0xFF07 => self.timer.read(addr),
-            0xFF0F => self.interrupt_flag,
-            0xFF10...0xFF3F => 0, /* Sound I/O Ports, sound not implemented for now. */
-            0xFF40...0xFF4B => self.ppu.read_reg(addr),
+            0xFF01..=0xFF02 => self.serial.read(addr),
+            0xFF04..=0xFF07 => self.timer.read(addr),
+            0xFF0F => self.interrupt_flag | 0xE0, /* Upper 3 bits are unused and read as 1. */
+            0xFF10..=0xFF3F => 0, /* Sound I/O Ports, sound not implemented for now. */
+            0xFF46 => self.oam_dma,
+            0xFF40..=0xFF4B => self.ppu.read_reg(addr),
+            0xFF03 | 0xFF08..=0xFF0E => 0xFF, /* Unmapped */
             _ => panic!("Illegal I/O port address"),
         }
     }
@@ -105,39 +126,215 @@ impl MMU {
 
     pub fn write(&mut self, addr: u16, value: u8) {
         match addr {
-            0x0000...0x7FFF => self.mbc.write_rom(addr, value),
-            0x8000...0x9FFF => self.ppu.write_vram(addr, value), /* 8KB Video RAM (VRAM) */
-            0xC000...0xDFFF => self.ram[(addr - 0xC000) as usize] = value,
-            0xE000...0xFDFF => self.write(addr - 0x2000, value),
-            0xFE00...0xFE9F => self.ppu.write_oam(addr, value),
-            0xFF00...0xFF4B | 0xFF50 => self.write_io_port(addr, value),
-            0xFF4C...0xFF4F | 0xFF51...0xFF7F => self.empty_ram[(addr - 0xFF4C) as usize] = value,
-            0xFF80...0xFFFE => self.high_ram[(addr - 0xFF80) as usize] = value,
-            0xFEA0...0xFFEF => {},
+            0x0000..=0x7FFF => self.mbc.write_rom(addr, value),
+            0x8000..=0x9FFF => self.ppu.write_vram(addr, value), /* 8KB Video RAM (VRAM) */
+            0xA000..=0xBFFF => {
+                if self.mbc.write_ram(addr, value) {
+                    self.save_dirty = true;
+                }
+            }
+            0xC000..=0xDFFF => self.ram[(addr - 0xC000) as usize] = value,
+            0xE000..=0xFDFF => self.write(addr - 0x2000, value),
+            0xFE00..=0xFE9F => self.ppu.write_oam(addr, value),
+            0xFF00..=0xFF4B | 0xFF50 => self.write_io_port(addr, value),
+            0xFF4C..=0xFF4F | 0xFF51..=0xFF7F => {},
+            0xFF80..=0xFFFE => self.high_ram[(addr - 0xFF80) as usize] = value,
+            0xFEA0..=0xFEFF => {},
             0xFFFF => self.interrupt_enable = value,
-            _ => panic!("Unimplemented memory access at addr {:4X}", addr),
         }
     }
 
     pub fn write_io_port(&mut self, addr: u16, value: u8) {
         match addr {
-            0xFF00 => self.joypad.write(value),
-            0xFF01...0xFF02 => eprintln!("Serial Data Transfer registers not implemented"),
-            0xFF04...0xFF07 => self.timer.write(addr, value),
-            0xFF0F => self.interrupt_flag = value,
-            0xFF10...0xFF3F => (), /* Sound I/O Ports, sound not implemented for now. */
-            0xFF40...0xFF4B => self.ppu.write_reg(addr, value),
+            0xFF00 => {
+                if self.joypad.write(value) {
+                    self.request_interrupt(Interrupt::Joypad);
+                }
+            }
+            0xFF01..=0xFF02 => self.serial.write(addr, value),
+            0xFF04..=0xFF07 => self.timer.write(addr, value),
+            0xFF0F => self.interrupt_flag = value & 0x1F,
+            0xFF10..=0xFF3F => (), /* Sound I/O Ports, sound not implemented for now. */
+            0xFF46 => self.do_oam_dma(value),
+            0xFF40..=0xFF4B => self.ppu.write_reg(addr, value),
             0xFF50 => self.dmg_disabled = value > 0,
+            0xFF03 | 0xFF08..=0xFF0E => (), /* Unmapped */
             _ => panic!("Illegal I/O port address"),
         }
     }
 
+    /* Copies 160 bytes from 0xXX00 to the OAM. The real transfer takes 160 M-cycles during which
+     * the CPU can only access HRAM, games wait in HRAM anyway so it's done at once here.
+     */
+    fn do_oam_dma(&mut self, value: u8) {
+        /* Sources past the internal RAM read its echo, the DMA can't see OAM nor I/O ports. */
+        let source = match value {
+            0xE0..=0xFF => ((value - 0x20) as u16) << 8,
+            _ => (value as u16) << 8,
+        };
+        let mut data = [0; ppu::OAM_SZ];
+
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = self.read(source + i as u16);
+        }
+
+        self.oam_dma = value;
+        self.ppu.write_oam_dma(&data);
+    }
+
     pub fn write_wide(&mut self, addr: u16, value: u16) {
         self.write(addr + 1, (value >> 8) as u8);
         self.write(addr, value as u8);
     }
 
-    pub fn is_dmg_disabled(&self) -> bool {
-        self.dmg_disabled
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.mask();
+    }
+
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag &= !interrupt.mask();
+    }
+
+    /* Highest priority interrupt both requested in IF and enabled in IE. */
+    pub fn next_pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.interrupt_enable & self.interrupt_flag;
+
+        interrupt::INTERRUPTS
+            .iter()
+            .find(|interrupt| pending & interrupt.mask() > 0)
+            .cloned()
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.ppu.framebuffer()
+    }
+
+    pub fn take_frame_ready(&mut self) -> bool {
+        self.ppu.take_frame_ready()
+    }
+
+    pub fn set_buttons(&mut self, buttons: joypad::Buttons) {
+        if self.joypad.set_buttons(buttons) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial.take_output()
+    }
+
+    pub fn has_pending_interrupt(&self) -> bool {
+        self.interrupt_enable & self.interrupt_flag & 0x1F > 0
+    }
+
+    pub fn sync_rtc_to_host(&mut self) {
+        self.mbc.sync_rtc_to_host();
+    }
+
+    pub fn save_data(&self) -> Vec<u8> {
+        self.mbc.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mbc.load_save_data(data);
+    }
+
+    /* Returns whether the cartridge RAM or clock changed since the last call. */
+    pub fn take_save_dirty(&mut self) -> bool {
+        let dirty = self.save_dirty;
+        self.save_dirty = false;
+        dirty
+    }
+
+    pub fn infrared(&mut self) -> Option<&mut Infrared> {
+        self.mbc.infrared()
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.mbc.set_tilt(x, y);
+    }
+
+    pub fn camera(&mut self) -> Option<&mut Camera> {
+        self.mbc.camera()
+    }
+
+    pub fn is_rumbling(&self) -> bool {
+        self.mbc.is_rumbling()
+    }
+
+    /* Used to wake up from STOP mode. */
+    pub fn is_joypad_line_low(&self) -> bool {
+        self.joypad.read() & 0x0F != 0x0F
+    }
+
+    /* Replaces the built-in DMG boot ROM, which has to be DMG_ROM_SIZE bytes long. */
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        debug_assert_eq!(boot_rom.len(), DMG_ROM_SIZE, "Invalid boot ROM size");
+        self.boot_rom = boot_rom;
+    }
+
+    /* Puts the I/O registers in the state the DMG boot ROM leaves them in. */
+    pub fn skip_boot_rom(&mut self) {
+        self.write(0xFF40, 0x91); /* LCD and background on */
+        self.write(0xFF47, 0xFC); /* BGP */
+        self.write(0xFF48, 0xFF); /* OBP0 */
+        self.write(0xFF49, 0xFF); /* OBP1 */
+        self.write(0xFF50, 0x01); /* Unmap the boot ROM */
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::header::CartridgeHeader;
+
+    fn rom_only_mmu() -> MMU {
+        let rom = vec![0; 0x8000];
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        let mapper = mbc::detect_mapper(&header, &rom).unwrap();
+
+        MMU::new(mbc::load_cartridge(&header, mapper, rom))
+    }
+
+    #[test]
+    fn io_registers() {
+        let mut mmu = rom_only_mmu();
+
+        /* Every register can be accessed, whether something is there or not. */
+        for addr in 0xFF00..=0xFF7F {
+            mmu.write(addr, 0x00);
+            mmu.read(addr);
+        }
+
+        /* Nothing is there on the DMG. CGB registers like KEY1 (0xFF4D) have to read as 0xFF, or
+         * software assumes it runs on a CGB.
+         */
+        let unmapped = (0xFF08..=0xFF0E).chain(0xFF4C..=0xFF4F).chain(0xFF51..=0xFF7F);
+        for addr in std::iter::once(0xFF03).chain(unmapped) {
+            assert_eq!(mmu.read(addr), 0xFF, "{:04X}", addr);
+        }
+    }
+
+    #[test]
+    fn save_dirty_on_ram_change() {
+        /* MBC1 with 8KB of battery backed RAM. */
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        let mapper = mbc::detect_mapper(&header, &rom).unwrap();
+        let mut mmu = MMU::new(mbc::load_cartridge(&header, mapper, rom));
+        mmu.write(0x0000, 0x0A);
+
+        mmu.write(0xA000, 0x00);
+        assert!(!mmu.take_save_dirty());
+
+        mmu.write(0xA000, 0x42);
+        assert!(mmu.take_save_dirty());
+        assert!(!mmu.take_save_dirty());
+
+        mmu.write(0xA000, 0x42);
+        assert!(!mmu.take_save_dirty());
     }
 }
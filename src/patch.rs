@@ -0,0 +1,519 @@
+/* ROM patches, as used by translations and hacks. They're applied on the ROM bytes in memory, the
+ * files on disk are never modified.
+ */
+
+use std::error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::header::MAX_ROM_SIZE;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+const UPS_MAGIC: &[u8] = b"UPS1";
+
+/* BPS and UPS both end with the source, target and patch CRC32s. */
+const FOOTER_SIZE: usize = 12;
+/* Enough for any 64 bits number, longer ones only come from corrupted patches. */
+const MAX_NUMBER_BYTES: usize = 10;
+
+/* Looked for next to the ROM, in this order. */
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    /* The patch ends in the middle of a record. */
+    Truncated,
+    /* The patch is for another ROM, or another revision of it. */
+    SourceMismatch { expected: u32, actual: u32 },
+    TargetMismatch { expected: u32, actual: u32 },
+    /* The patch file itself is corrupted. */
+    PatchMismatch { expected: u32, actual: u32 },
+    /* A copy refers to data outside of the source or target. */
+    OutOfBounds,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "unknown patch format, expected IPS, BPS or UPS"),
+            PatchError::Truncated => write!(f, "truncated patch"),
+            PatchError::SourceMismatch { expected, actual } => {
+                write!(f, "patch made for another ROM (CRC32 {:08X}, got {:08X})", expected, actual)
+            }
+            PatchError::TargetMismatch { expected, actual } => {
+                write!(f, "patched ROM CRC32 is {:08X}, expected {:08X}", actual, expected)
+            }
+            PatchError::PatchMismatch { expected, actual } => {
+                write!(f, "corrupted patch (CRC32 {:08X}, expected {:08X})", actual, expected)
+            }
+            PatchError::OutOfBounds => write!(f, "patch copies data out of bounds"),
+        }
+    }
+}
+
+impl error::Error for PatchError {}
+
+/* The format is detected from the patch header. */
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+/* <rom>.ips, <rom>.bps or <rom>.ups, whichever exists first. */
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| rom_path.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+/* Reads through a patch, every read failing with PatchError::Truncated past its end. */
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(PatchError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(len)?.iter().fold(0, |val, &byte| val << 8 | byte as usize))
+    }
+
+    /* Variable length number used by BPS and UPS, 7 bits at a time with the high bit marking
+     * the last byte.
+     */
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut val: usize = 0;
+        let mut shift: usize = 1;
+
+        for _ in 0..MAX_NUMBER_BYTES {
+            let byte = self.byte()?;
+            val = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|bits| val.checked_add(bits))
+                .ok_or(PatchError::OutOfBounds)?;
+
+            if byte & 0x80 != 0 {
+                return Ok(val);
+            }
+
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            val = val.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+
+        Err(PatchError::OutOfBounds)
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+    let mut target = rom.to_vec();
+
+    loop {
+        if reader.bytes(IPS_EOF.len())? == IPS_EOF {
+            break;
+        }
+        reader.pos -= IPS_EOF.len();
+
+        let offset = reader.big_endian(3)?;
+        let (len, data) = match reader.big_endian(2)? {
+            /* Run length encoded record. */
+            0 => {
+                let len = reader.big_endian(2)?;
+                (len, vec![reader.byte()?; len])
+            }
+            len => (len, reader.bytes(len)?.to_vec()),
+        };
+
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        target[offset..offset + len].copy_from_slice(&data);
+    }
+
+    /* Optional extension truncating the ROM. */
+    if let Ok(len) = reader.big_endian(3) {
+        target.truncate(len);
+    }
+
+    Ok(target)
+}
+
+/* Checks the 3 CRC32s at the end of a BPS or UPS patch against the source and the patch itself,
+ * returns the expected target CRC32.
+ */
+fn check_footer(source: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    let footer = patch.len().checked_sub(FOOTER_SIZE).ok_or(PatchError::Truncated)?;
+    let crc = |pos: usize| u32::from_le_bytes([patch[pos], patch[pos + 1], patch[pos + 2], patch[pos + 3]]);
+
+    let expected = crc(footer + 8);
+    let actual = crc32(&patch[..footer + 8]);
+    if expected != actual {
+        return Err(PatchError::PatchMismatch { expected, actual });
+    }
+
+    let expected = crc(footer);
+    let actual = crc32(source);
+    if expected != actual {
+        return Err(PatchError::SourceMismatch { expected, actual });
+    }
+
+    Ok(crc(footer + 4))
+}
+
+/* The target size comes from the patch, don't allocate more than any ROM could need. */
+fn check_target_size(size: usize) -> Result<usize, PatchError> {
+    match size {
+        size if size <= MAX_ROM_SIZE => Ok(size),
+        _ => Err(PatchError::OutOfBounds),
+    }
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    match crc32(target) {
+        actual if actual == expected => Ok(()),
+        actual => Err(PatchError::TargetMismatch { expected, actual }),
+    }
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    const SOURCE_READ: usize = 0;
+    const TARGET_READ: usize = 1;
+    const SOURCE_COPY: usize = 2;
+    const TARGET_COPY: usize = 3;
+
+    let target_crc = check_footer(source, patch)?;
+    let actions_end = patch.len() - FOOTER_SIZE;
+
+    let mut reader = Reader::new(&patch[..actions_end], BPS_MAGIC.len());
+    let _source_size = reader.number()?;
+    let target_size = check_target_size(reader.number()?)?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    while reader.pos < actions_end {
+        let action = reader.number()?;
+        let len = (action >> 2) + 1;
+
+        /* Also bounds the copies below, TARGET_COPY could otherwise grow the target forever. */
+        if len > target_size - target.len() {
+            return Err(PatchError::OutOfBounds);
+        }
+
+        match action & 0x03 {
+            SOURCE_READ => {
+                let pos = target.len();
+                let data = source.get(pos..pos + len).ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(data);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_offset = relative_offset(source_offset, reader.number()?)?;
+                let end = source_offset.checked_add(len).ok_or(PatchError::OutOfBounds)?;
+                let data = source.get(source_offset..end).ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(data);
+                source_offset += len;
+            }
+            TARGET_COPY => {
+                target_offset = relative_offset(target_offset, reader.number()?)?;
+                /* The copy can overlap the bytes it's writing, so go one byte at a time. */
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    check_target(&target, target_crc)?;
+
+    Ok(target)
+}
+
+/* BPS offsets are relative to the previous one, the lowest bit being the sign. */
+fn relative_offset(offset: usize, encoded: usize) -> Result<usize, PatchError> {
+    let delta = encoded >> 1;
+
+    match encoded & 1 {
+        0 => offset.checked_add(delta),
+        _ => offset.checked_sub(delta),
+    }
+    .ok_or(PatchError::OutOfBounds)
+}
+
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(source, patch)?;
+    let hunks_end = patch.len() - FOOTER_SIZE;
+
+    let mut reader = Reader::new(&patch[..hunks_end], UPS_MAGIC.len());
+    let _source_size = reader.number()?;
+    let target_size = check_target_size(reader.number()?)?;
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let mut pos: usize = 0;
+
+    while reader.pos < hunks_end {
+        pos = pos.checked_add(reader.number()?).ok_or(PatchError::OutOfBounds)?;
+
+        /* XORed bytes up to a 0 one. */
+        loop {
+            let byte = reader.byte()?;
+            let offset = pos;
+            pos = pos.checked_add(1).ok_or(PatchError::OutOfBounds)?;
+            if byte == 0 {
+                break;
+            }
+
+            *target.get_mut(offset).ok_or(PatchError::OutOfBounds)? ^= byte;
+        }
+    }
+
+    check_target(&target, target_crc)?;
+
+    Ok(target)
+}
+
+/* Same CRC32 as zip and PNG (reflected 0xEDB88320 polynomial). */
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB8_8320,
+            _ => crc >> 1,
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(mut val: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        loop {
+            let byte = (val & 0x7F) as u8;
+            val >>= 7;
+
+            if val == 0 {
+                bytes.push(byte | 0x80);
+                return bytes;
+            }
+
+            bytes.push(byte);
+            val -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    /* A BPS patch without metadata. */
+    fn bps_patch(source: &[u8], target_size: usize, actions: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target_size));
+        patch.extend(number(0));
+        patch.extend_from_slice(actions);
+        with_footer(patch, source, target)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn number_roundtrip() {
+        for &val in &[0, 1, 127, 128, 300, 0x4000, 0x12_3456] {
+            assert_eq!(Reader::new(&number(val), 0).number(), Ok(val));
+        }
+    }
+
+    #[test]
+    fn malformed_number() {
+        assert_eq!(Reader::new(&[0x7F; 12], 0).number(), Err(PatchError::OutOfBounds));
+        assert_eq!(Reader::new(&[0x00; 12], 0).number(), Err(PatchError::OutOfBounds));
+        assert_eq!(Reader::new(&[0x7F, 0x7F], 0).number(), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+        /* "XY" at 1. */
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, b'X', b'Y']);
+        /* 3 times 'Z' at 6, growing the ROM. */
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, b'Z']);
+        patch.extend_from_slice(b"EOF");
+
+        assert_eq!(apply_patch(b"abcde", &patch).unwrap(), b"aXYde\0ZZZ");
+
+        /* Truncation extension. */
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply_patch(b"abcde", &patch).unwrap(), b"aX");
+
+        assert_eq!(apply_patch(b"abcde", &patch[..8]), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn bps() {
+        let source = b"hello world";
+        let target = b"hello, hello world!";
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        /* SourceRead "hello" */
+        patch.extend(number((5 - 1) << 2));
+        /* TargetRead ", " */
+        patch.extend(number((2 - 1) << 2 | 1));
+        patch.extend_from_slice(b", ");
+        /* TargetCopy "hello" from 0 */
+        patch.extend(number((5 - 1) << 2 | 3));
+        patch.extend(number(0));
+        /* SourceCopy " world" from 5 */
+        patch.extend(number((6 - 1) << 2 | 2));
+        patch.extend(number(5 << 1));
+        /* TargetRead "!" */
+        patch.extend(number(1));
+        patch.extend_from_slice(b"!");
+        let patch = with_footer(patch, source, target);
+
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+
+        assert!(matches!(apply_patch(b"hello there", &patch), Err(PatchError::SourceMismatch { .. })));
+
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 0xFF;
+        assert!(matches!(apply_patch(source, &corrupted), Err(PatchError::PatchMismatch { .. })));
+    }
+
+    #[test]
+    fn bps_target_mismatch() {
+        /* SourceRead "hello", with the footer claiming another target. */
+        let patch = bps_patch(b"hello", 5, &number((5 - 1) << 2), b"jello");
+
+        assert_eq!(
+            apply_patch(b"hello", &patch),
+            Err(PatchError::TargetMismatch { expected: crc32(b"jello"), actual: crc32(b"hello") })
+        );
+    }
+
+    #[test]
+    fn bps_out_of_bounds() {
+        let patch = bps_patch(b"hello", MAX_ROM_SIZE + 1, &number((5 - 1) << 2), b"hello");
+        assert_eq!(apply_patch(b"hello", &patch), Err(PatchError::OutOfBounds));
+
+        /* SourceRead past the end of the source. */
+        let patch = bps_patch(b"hello", 8, &number((8 - 1) << 2), b"hello");
+        assert_eq!(apply_patch(b"hello", &patch), Err(PatchError::OutOfBounds));
+
+        /* TargetRead "h", then a TargetCopy of it going on way past the target size. */
+        let mut actions = number(1);
+        actions.push(b'h');
+        actions.extend(number((0x10_0000 - 1) << 2 | 3));
+        actions.extend(number(0));
+        let patch = bps_patch(b"hello", 5, &actions, b"hhhhh");
+        assert_eq!(apply_patch(b"hello", &patch), Err(PatchError::OutOfBounds));
+
+        /* SourceCopy from before the start of the source. */
+        let mut actions = number(2);
+        actions.extend(number(1 << 1 | 1));
+        let patch = bps_patch(b"hello", 1, &actions, b"h");
+        assert_eq!(apply_patch(b"hello", &patch), Err(PatchError::OutOfBounds));
+    }
+
+    #[test]
+    fn ups() {
+        let source = b"abcdef";
+        let target = b"aXcdefgh";
+
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(1));
+        patch.extend_from_slice(&[b'b' ^ b'X', 0x00]);
+        patch.extend(number(3));
+        patch.extend_from_slice(&[b'g', b'h', 0x00]);
+        let patch = with_footer(patch, source, target);
+
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn ups_out_of_bounds() {
+        let source = b"abcdef";
+
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(MAX_ROM_SIZE + 1));
+        let patch = with_footer(patch, source, source);
+        assert_eq!(apply_patch(source, &patch), Err(PatchError::OutOfBounds));
+
+        /* XORing past the end of the target. */
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(source.len()));
+        patch.extend(number(6));
+        patch.extend_from_slice(&[0x01, 0x00]);
+        let patch = with_footer(patch, source, source);
+        assert_eq!(apply_patch(source, &patch), Err(PatchError::OutOfBounds));
+
+        /* Skipping to the very end of the address space. */
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(source.len()));
+        patch.extend(number(usize::MAX));
+        patch.push(0x00);
+        let patch = with_footer(patch, source, source);
+        assert_eq!(apply_patch(source, &patch), Err(PatchError::OutOfBounds));
+
+        /* Malformed patch, ending in the middle of a hunk. */
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(source.len()));
+        patch.extend(number(1));
+        patch.push(0x01);
+        let patch = with_footer(patch, source, source);
+        assert_eq!(apply_patch(source, &patch), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(apply_patch(b"rom", b"NOTAPATCH"), Err(PatchError::UnknownFormat));
+    }
+}
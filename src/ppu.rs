@@ -1,22 +1,36 @@
-use std::collections::HashMap;
+use crate::framebuffer::{Framebuffer, SCREEN_WIDTH};
+use crate::interrupt::Interrupt;
 use crate::palette::Palette;
-use crate::lcd::LCD;
 
-const VIEWPORT_WIDTH: usize = 160;
-const VIEWPORT_HEIGHT: usize = 144;
 const SCREEN_WIDTH_IN_TILES: usize = 32;
 
 const VRAM_SIZE: usize = 0x2000;
 const VRAM_START_ADDR: usize = 0x8000;
 
-const BG_TILEMAP_SZ: usize = 0x400;
-const BG_TILEDATA_SZ: usize = 0x1000;
-
-const OAM_SZ: usize = 160;
+pub const OAM_SZ: usize = 160;
 const OAM_START_ADDR: usize = 0xFE00;
 
 const TILE_SZ: usize = 16;
 
+/* Each OAM entry is 4 bytes: Y, X, tile number and attributes. */
+const SPRITE_SZ: usize = 4;
+const SPRITES_PER_LINE: usize = 10;
+
+/* Sprite attributes. */
+const SPRITE_BEHIND_BG: u8 = 1 << 7;
+const SPRITE_Y_FLIP: u8 = 1 << 6;
+const SPRITE_X_FLIP: u8 = 1 << 5;
+const SPRITE_OBP1: u8 = 1 << 4;
+
+/* An OAM entry, with its position already converted to screen coordinates. */
+#[derive(Clone, Copy, Debug)]
+struct Sprite {
+    x: i16,
+    y: i16,
+    tile: u8,
+    attributes: u8,
+}
+
 #[derive(Debug)]
 enum LCDMode {
     HBlank, /* Mode 0 */
@@ -33,16 +47,37 @@ pub struct PPU {
     ly: u8,
     lyc: u8,
     bgp: Palette,
-    obp0: u8,
-    obp1: u8,
+    obp0: Palette,
+    obp1: Palette,
     wy: u8,
     wx: u8,
     vram: [u8; VRAM_SIZE],
     oam: [u8; OAM_SZ],
 
-    lcd: LCD,
+    /* Sprites found by the OAM search on the current line, in drawing priority order. */
+    line_sprites: Vec<Sprite>,
+    /* Color numbers of the background on the current line, before the palette. Sprites behind the
+     * background only show over color 0.
+     */
+    bg_colors: [u8; SCREEN_WIDTH],
+
+    /* Set once LY matched WY during the frame, the window can only show from then on. */
+    window_triggered: bool,
+    /* Line of the window to draw next, only advances on lines where the window was drawn. */
+    window_line: u8,
+    /* With WX = 166 the window also covers the whole next line. */
+    window_wraps: bool,
+
+    framebuffer: Framebuffer,
+    /* Set once a whole frame has been rendered, until the frontend takes it. */
+    frame_ready: bool,
 
     cycles_remaining: usize,
+
+    /* The STAT interrupt is requested on a rising edge of this line. */
+    stat_line: bool,
+    /* Interrupts requested since the last call to do_cycle. */
+    interrupts: u8,
 }
 
 impl PPU {
@@ -55,34 +90,53 @@ impl PPU {
             ly:   0x00,
             lyc:  0x00,
             bgp:  Palette::new(0),
-            obp0: 0x00,
-            obp1: 0x00,
+            obp0: Palette::new(0),
+            obp1: Palette::new(0),
             wy:   0x00,
             wx:   0x00,
             vram: [0; VRAM_SIZE],
             oam: [0; OAM_SZ],
 
-            lcd: LCD::new(VIEWPORT_WIDTH, VIEWPORT_HEIGHT),
+            line_sprites: Vec::with_capacity(SPRITES_PER_LINE),
+            bg_colors: [0; SCREEN_WIDTH],
+
+            window_triggered: false,
+            window_line: 0,
+            window_wraps: false,
+
+            framebuffer: Framebuffer::new(),
+            frame_ready: false,
 
             cycles_remaining: 0,
+
+            stat_line: false,
+            interrupts: 0,
         }
     }
 
-    pub fn get_lcd_ref(&self) -> &LCD {
-        &self.lcd
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /* Returns true, only once, when a new frame is available in the framebuffer. */
+    pub fn take_frame_ready(&mut self) -> bool {
+        let frame_ready = self.frame_ready;
+        self.frame_ready = false;
+
+        frame_ready
     }
 
     pub fn read_reg(&self, addr: u16) -> u8 {
         match addr {
             0xFF40 => self.lcdc,
-            0xFF41 => self.stat,
+            0xFF41 => self.stat | 0x80, /* Bit 7 is unused and reads as 1. */
             0xFF42 => self.scy,
             0xFF43 => self.scx,
             0xFF44 => self.ly,
             0xFF45 => self.lyc,
             0xFF47 => self.bgp.register,
-            0xFF48 => self.obp0,
-            0xFF49 => self.obp1,
+            0xFF48 => self.obp0.register,
+            0xFF49 => self.obp1.register,
             0xFF4A => self.wy,
             0xFF4B => self.wx,
             _ => panic!("Invalid memory access on PPU register(addr = {:4X})", addr),
@@ -106,7 +160,7 @@ impl PPU {
     }
 
     pub fn read_oam(&self, addr: u16) -> u8 {
-        if self.is_vram_accessible() {
+        if self.is_oam_accessible() {
             let index = addr as usize - OAM_START_ADDR;
             self.oam[index]
         } else {
@@ -115,12 +169,17 @@ impl PPU {
     }
 
     pub fn write_oam(&mut self, addr: u16, val: u8) {
-        if self.is_vram_accessible() {
+        if self.is_oam_accessible() {
             let index = addr as usize - OAM_START_ADDR;
             self.oam[index] = val;
         }
     }
 
+    /* OAM DMA writes to the OAM whatever the mode is. */
+    pub fn write_oam_dma(&mut self, data: &[u8; OAM_SZ]) {
+        self.oam = *data;
+    }
+
     fn is_vram_accessible(&self) -> bool {
         let mode = self.get_mode();
 
@@ -145,79 +204,149 @@ impl PPU {
 
     pub fn write_reg(&mut self, addr: u16, val: u8) {
         match addr {
-            0xFF40 => self.lcdc = val,
-            0xFF41 => self.stat = val,
+            0xFF40 => self.write_lcdc(val),
+            0xFF41 => {
+                /* Mode and coincidence bits are read only. */
+                self.stat = (self.stat & 0b111) | (val & 0b0111_1000);
+                self.update_stat_line();
+            }
             0xFF42 => self.scy = val,
             0xFF43 => self.scx = val,
-            0xFF44 => self.ly = val,
-            0xFF45 => self.lyc = val,
+            0xFF44 => {}, /* LY is read only. */
+            0xFF45 => {
+                self.lyc = val;
+                self.update_coincidence();
+            }
             0xFF47 => self.bgp = Palette::new(val),
-            0xFF48 => self.obp0 = val,
-            0xFF49 => self.obp1 = val,
+            0xFF48 => self.obp0 = Palette::new(val),
+            0xFF49 => self.obp1 = Palette::new(val),
             0xFF4A => self.wy = val,
             0xFF4B => self.wx = val,
             _ => panic!("Invalid memory access on LCD (addr = {:4X})", addr),
         }
     }
 
-    pub fn do_cycle(&mut self) {
+    fn write_lcdc(&mut self, val: u8) {
+        let was_disabled = self.is_lcd_disabled();
+        self.lcdc = val;
+
         if self.is_lcd_disabled() {
-            return;
+            /* LY is reset and the PPU stays in HBlank while the LCD is off. */
+            self.ly = 0;
+            self.cycles_remaining = 0;
+            self.set_mode(LCDMode::HBlank);
+        } else if was_disabled {
+            /* The first line starts right away when the LCD gets turned on. */
+            self.ly = 0;
+            self.cycles_remaining = 80;
+            self.reset_window();
+            self.set_mode(LCDMode::OAMSearch);
+            self.update_coincidence();
+        }
+    }
+
+    /* Returns the interrupts requested during this cycle as IF bits. */
+    pub fn do_cycle(&mut self) -> u8 {
+        if self.is_lcd_disabled() {
+            return 0;
         }
 
         if self.is_mode_finished() {
             self.cycles_remaining += self.step_through_modes();
-        } else {
-            self.cycles_remaining -= 1;
         }
 
+        /* The cycle we are in right now counts towards the current mode. */
+        self.cycles_remaining -= 1;
+
+        let interrupts = self.interrupts;
+        self.interrupts = 0;
+
+        interrupts
     }
 
     fn is_mode_finished(&self) -> bool {
         self.cycles_remaining == 0
     }
 
+    /* Leaves the current mode and returns the duration of the next one. */
     fn step_through_modes(&mut self) -> usize {
         match self.get_mode() {
             LCDMode::OAMSearch => {
-                /* TODO: implement OAM */
-
-                /* Just transition into VRAM Transfer. */
+                self.search_oam();
                 self.set_mode(LCDMode::Transfer);
 
-                80
+                172
             },
             LCDMode::Transfer => {
                 self.render_bg_line();
+                self.render_window_line();
+                self.render_sprite_line();
                 self.set_mode(LCDMode::HBlank);
 
-                172
+                204
             },
             LCDMode::HBlank => {
-                if self.ly == 143 {
+                self.ly += 1;
+                self.update_coincidence();
+
+                if self.ly == 144 {
                     self.set_mode(LCDMode::VBlank);
+                    self.interrupts |= Interrupt::VBlank.mask();
+                    self.frame_ready = true;
+
+                    456
                 } else {
                     self.set_mode(LCDMode::OAMSearch);
-                }
 
-                self.ly += 1;
-
-                204
+                    80
+                }
             },
             LCDMode::VBlank    => {
+                self.ly += 1;
+
                 if self.ly > 153 {
-                    self.lcd.update();
                     self.ly = 0;
+                    self.reset_window();
+                    self.update_coincidence();
                     self.set_mode(LCDMode::OAMSearch);
+
+                    80
                 } else {
-                    self.ly += 1;
-                }
+                    self.update_coincidence();
 
-                456
+                    456
+                }
             },
         }
     }
 
+    fn update_coincidence(&mut self) {
+        match self.ly == self.lyc {
+            true => self.stat |= 1 << 2,
+            false => self.stat &= !(1 << 2),
+        }
+
+        self.update_stat_line();
+    }
+
+    fn update_stat_line(&mut self) {
+        let mode_source = match self.get_mode() {
+            LCDMode::HBlank => 1 << 3,
+            LCDMode::VBlank => 1 << 4,
+            LCDMode::OAMSearch => 1 << 5,
+            LCDMode::Transfer => 0,
+        };
+        let coincidence = self.stat & (1 << 2) > 0 && self.stat & (1 << 6) > 0;
+
+        let stat_line = !self.is_lcd_disabled() && (coincidence || self.stat & mode_source > 0);
+
+        if stat_line && !self.stat_line {
+            self.interrupts |= Interrupt::LCDStat.mask();
+        }
+
+        self.stat_line = stat_line;
+    }
+
     fn get_mode(&self) -> LCDMode {
         match self.stat & 0b11 {
             0 => LCDMode::HBlank,
@@ -238,6 +367,8 @@ impl PPU {
             LCDMode::OAMSearch => 2,
             LCDMode::Transfer => 3,
         };
+
+        self.update_stat_line();
     }
 
 
@@ -245,32 +376,204 @@ impl PPU {
         let tile_row = self.current_tile_row();
         let mut tile_col = self.current_tile_col();
 
-        let mut tiledata = self.fetch_tile(tile_row, tile_col);
+        let mut tiledata = self.fetch_tile(self.tilemap_offset(), tile_col, tile_row);
 
         /* Current coordinates in tile being drawn. */
         let mut x = self.scx % 8;
-        let y = ((self.scy + self.ly) % 8) as usize * 2;
+        let y = (self.scy.wrapping_add(self.ly) % 8) as usize * 2;
+
+        for n in 0..SCREEN_WIDTH {
+            let lsb = (tiledata[y] >> (7 - x)) & 1;
+            let msb = (tiledata[y + 1] >> (7 - x)) & 1;
+            let color = (msb << 1) | lsb;
+
+            /* Turned off, the background is white whatever BGP says, but still color 0 for the
+             * sprites behind it.
+             */
+            let shade = match self.is_bg_enabled() {
+                true => self.bgp.to_shade(color),
+                false => 0,
+            };
+
+            self.bg_colors[n] = color;
+            self.framebuffer.set_pixel(n, self.ly as usize, shade);
+
+            x += 1;
+            if x == 8 {
+                x = 0;
+                /* The background map wraps around horizontally. */
+                tile_col = (tile_col + 1) % SCREEN_WIDTH_IN_TILES;
+                tiledata = self.fetch_tile(self.tilemap_offset(), tile_col, tile_row);
+            }
+        }
+    }
 
-        for n in 0..160 {
-            unsafe {
-                let lsb = if ((*tiledata)[y] & ( 1 << (7 - x))) > 0 { 1 } else { 0 };
-                let msb = if ((*tiledata)[y + 1] & (1 << (7 - x))) > 0 { 2 } else { 0 };
-                let color = self.bgp.to_argb(lsb + msb);
+    fn is_window_enabled(&self) -> bool {
+        self.lcdc & (1 << 5) > 0
+    }
 
-                self.lcd.set_pixel(n, self.ly as usize, color as u32);
+    fn reset_window(&mut self) {
+        self.window_triggered = false;
+        self.window_line = 0;
+        self.window_wraps = false;
+    }
+
+    /* The window is drawn over the background from WX - 7, starting from its top left corner
+     * whatever the scroll registers are. With WX < 7 its first columns are cut off.
+     */
+    fn render_window_line(&mut self) {
+        if self.ly == self.wy {
+            self.window_triggered = true;
+        }
+
+        let wraps = self.window_wraps;
+        self.window_wraps = false;
+
+        /* On the DMG, turning the background off hides the window too. */
+        if !self.is_window_enabled() || !self.is_bg_enabled() || !self.window_triggered {
+            return;
+        }
+
+        let start = match wraps {
+            true => 0,
+            false if self.wx <= 166 => self.wx as isize - 7,
+            false => return,
+        };
+
+        let first = start.max(0) as usize;
+        let row = self.window_line as usize;
+        let y = (row % 8) * 2;
 
-                x += 1;
-                if x == 8 {
-                    x = 0;
-                    tile_col += 1;
-                    tiledata = self.fetch_tile(tile_col, tile_row);
+        /* Current column inside the window. */
+        let mut col = (first as isize - start) as usize;
+        let mut tiledata = self.fetch_tile(self.window_tilemap_offset(), col / 8, row / 8);
+
+        for n in first..SCREEN_WIDTH {
+            let x = col % 8;
+            let lsb = (tiledata[y] >> (7 - x)) & 1;
+            let msb = (tiledata[y + 1] >> (7 - x)) & 1;
+            let color = (msb << 1) | lsb;
+
+            self.bg_colors[n] = color;
+            self.framebuffer.set_pixel(n, self.ly as usize, self.bgp.to_shade(color));
+
+            col += 1;
+            if x == 7 {
+                tiledata = self.fetch_tile(self.window_tilemap_offset(), col / 8, row / 8);
+            }
+        }
+
+        self.window_line += 1;
+        self.window_wraps = self.wx == 166;
+    }
+
+    fn sprite_height(&self) -> i16 {
+        match self.lcdc & (1 << 2) > 0 {
+            false => 8,
+            true => 16,
+        }
+    }
+
+    fn is_sprites_enabled(&self) -> bool {
+        self.lcdc & (1 << 1) > 0
+    }
+
+    /* Picks the first 10 sprites, in OAM order, overlapping the current line. Sprites hidden off
+     * the left or right of the screen still count.
+     */
+    fn search_oam(&mut self) {
+        let ly = self.ly as i16;
+        let height = self.sprite_height();
+
+        self.line_sprites.clear();
+
+        for entry in self.oam.chunks_exact(SPRITE_SZ) {
+            let sprite = Sprite {
+                y: entry[0] as i16 - 16,
+                x: entry[1] as i16 - 8,
+                tile: entry[2],
+                attributes: entry[3],
+            };
+
+            if ly >= sprite.y && ly < sprite.y + height {
+                self.line_sprites.push(sprite);
+
+                if self.line_sprites.len() == SPRITES_PER_LINE {
+                    break;
+                }
+            }
+        }
+
+        /* On the DMG the sprite with the smallest X is drawn on top, OAM order breaking ties. The
+         * sort is stable, so sorting by X alone keeps the OAM order among equal ones.
+         */
+        self.line_sprites.sort_by_key(|sprite| sprite.x);
+    }
+
+    fn render_sprite_line(&mut self) {
+        if !self.is_sprites_enabled() {
+            return;
+        }
+
+        let ly = self.ly as i16;
+        let height = self.sprite_height();
+
+        for n in 0..SCREEN_WIDTH {
+            let x = n as i16;
+
+            /* The first sprite with a non transparent pixel here wins, even when it ends up
+             * hidden behind the background.
+             */
+            let pixel = self
+                .line_sprites
+                .iter()
+                .filter(|sprite| x >= sprite.x && x < sprite.x + 8)
+                .find_map(|sprite| {
+                    let mut row = ly - sprite.y;
+                    if sprite.attributes & SPRITE_Y_FLIP > 0 {
+                        row = height - 1 - row;
+                    }
+
+                    let mut col = x - sprite.x;
+                    if sprite.attributes & SPRITE_X_FLIP > 0 {
+                        col = 7 - col;
+                    }
+
+                    /* 8x16 sprites ignore the lowest bit of the tile number. */
+                    let tile = match height {
+                        16 => sprite.tile & 0xFE,
+                        _ => sprite.tile,
+                    };
+
+                    /* Sprites always use the 0x8000 tile data. */
+                    let offset = tile as usize * TILE_SZ + row as usize * 2;
+                    let lsb = (self.vram[offset] >> (7 - col)) & 1;
+                    let msb = (self.vram[offset + 1] >> (7 - col)) & 1;
+
+                    /* Color 0 is transparent. */
+                    match (msb << 1) | lsb {
+                        0 => None,
+                        color => Some((sprite.attributes, color)),
+                    }
+                });
+
+            if let Some((attributes, color)) = pixel {
+                if attributes & SPRITE_BEHIND_BG > 0 && self.bg_colors[n] != 0 {
+                    continue;
                 }
+
+                let palette = match attributes & SPRITE_OBP1 > 0 {
+                    false => &self.obp0,
+                    true => &self.obp1,
+                };
+
+                self.framebuffer.set_pixel(n, self.ly as usize, palette.to_shade(color));
             }
         }
     }
 
     fn current_tile_row(&self) -> usize {
-        let (tile_row, _) = self.scy.overflowing_add(self.ly);
+        let tile_row = self.scy.wrapping_add(self.ly);
         (tile_row / 8) as usize
     }
 
@@ -278,33 +581,33 @@ impl PPU {
         (self.scx  / 8) as usize
     }
 
-    fn fetch_tile(&self, col: usize, row: usize) -> *const [u8] {
-        if self.is_bg_enabled() {
-            let tiledata_off = self.tile_offset(col, row);
+    fn fetch_tile(&self, tilemap: usize, col: usize, row: usize) -> [u8; TILE_SZ] {
+        let mut tiledata = [0; TILE_SZ];
 
-            &self.vram[tiledata_off..tiledata_off + TILE_SZ]
-        } else {
-            &[0; TILE_SZ]
+        if self.is_bg_enabled() {
+            let tiledata_off = self.tile_offset(tilemap, col, row);
+            tiledata.copy_from_slice(&self.vram[tiledata_off..tiledata_off + TILE_SZ]);
         }
+
+        tiledata
     }
 
     fn is_bg_enabled(&self) -> bool {
         self.lcdc & 1 > 0
     }
 
-    fn tile_offset(&self, col: usize, row: usize) -> usize {
+    fn tile_offset(&self, tilemap: usize, col: usize, row: usize) -> usize {
         /* Offset of tile number inside the tilemap. */
-        let mapoff = col + row * SCREEN_WIDTH_IN_TILES + self.tilemap_offset();
+        let mapoff = col + row * SCREEN_WIDTH_IN_TILES + tilemap;
+        let tile_number = self.vram[mapoff];
 
-        let tiledata_offset = if self.tiledata_offset()  == 0x8000 {
+        if self.lcdc & (1 << 4) > 0 {
             /* Tile numbers from 0 to 255 */
-            (self.vram[mapoff] as usize * TILE_SZ) + self.tiledata_offset()
+            (tile_number as usize * TILE_SZ) + self.tiledata_offset()
         } else {
             /* Tile numbers from -128 to 127 */
-            (self.vram[mapoff] as isize * TILE_SZ as isize) as usize + self.tiledata_offset()
-        };
-
-        tiledata_offset
+            (self.tiledata_offset() as isize + tile_number as i8 as isize * TILE_SZ as isize) as usize
+        }
     }
 
     fn tilemap_offset(&self) -> usize {
@@ -316,6 +619,15 @@ impl PPU {
         addr - VRAM_START_ADDR
     }
 
+    fn window_tilemap_offset(&self) -> usize {
+        let addr = match self.lcdc & (1 << 6) > 0 {
+            false => 0x9800,
+            true => 0x9C00,
+        };
+
+        addr - VRAM_START_ADDR
+    }
+
     fn tiledata_offset(&self) -> usize {
         let addr = match self.lcdc & (1 << 4) > 0 {
             false => 0x9000, /* Indexes are from -128 to 127 => pattern #0 at 0x9000 */
@@ -325,3 +637,209 @@ impl PPU {
         addr - VRAM_START_ADDR
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /* Tile 0: color 1 everywhere. */
+    const FULL_TILE: [u8; TILE_SZ] = [0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+    /* Tile 1: color 3 on the left half, color 0 (transparent for sprites) on the right. */
+    const LEFT_HALF_TILE: [u8; TILE_SZ] = [0xF0; TILE_SZ];
+
+    /* LCD, sprites and background on, 0x8000 tile data. */
+    const LCDC: u8 = 0x93;
+
+    /* The background is tile 0 everywhere, drawn white. OBP0 gives the color number as shade,
+     * OBP1 always dark gray.
+     */
+    fn ppu() -> PPU {
+        let mut ppu = PPU::new();
+
+        ppu.vram[..TILE_SZ].copy_from_slice(&FULL_TILE);
+        ppu.vram[TILE_SZ..2 * TILE_SZ].copy_from_slice(&LEFT_HALF_TILE);
+        ppu.write_reg(0xFF47, 0xE0);
+        ppu.write_reg(0xFF48, 0xE4);
+        ppu.write_reg(0xFF49, 0xAA);
+
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut PPU, index: usize, x: u8, y: u8, tile: u8, attributes: u8) {
+        ppu.oam[index * SPRITE_SZ..(index + 1) * SPRITE_SZ].copy_from_slice(&[y, x, tile, attributes]);
+    }
+
+    fn run_frame(ppu: &mut PPU, lcdc: u8) {
+        ppu.write_reg(0xFF40, lcdc);
+
+        while !ppu.take_frame_ready() {
+            ppu.do_cycle();
+        }
+    }
+
+    /* Runs until LY reaches the given line, at the start of its OAM search. */
+    fn run_until_line(ppu: &mut PPU, ly: u8) {
+        while ppu.ly != ly {
+            ppu.do_cycle();
+        }
+    }
+
+    /* Window map at 0x9C00 filled with the given tile. */
+    fn fill_window(ppu: &mut PPU, tile: u8) {
+        for offset in 0x1C00..0x2000 {
+            ppu.vram[offset] = tile;
+        }
+    }
+
+    #[test]
+    fn window_position() {
+        let mut ppu = ppu();
+        fill_window(&mut ppu, 1);
+
+        ppu.write_reg(0xFF4A, 0);
+        ppu.write_reg(0xFF4B, 7 + 80);
+        run_frame(&mut ppu, LCDC | 0x60);
+        assert_eq!(ppu.framebuffer().get_pixel(79, 0), 0);
+        assert_eq!(ppu.framebuffer().get_pixel(80, 0), 3);
+        assert_eq!(ppu.framebuffer().get_pixel(84, 0), 0);
+
+        /* With WX < 7 the left of the window is cut off. */
+        ppu.write_reg(0xFF4B, 3);
+        run_frame(&mut ppu, LCDC | 0x60);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 0), 0);
+        assert_eq!(ppu.framebuffer().get_pixel(4, 0), 3);
+
+        /* WX = 166 shows a single column, then the window covers the whole next line. */
+        ppu.write_reg(0xFF4B, 166);
+        run_frame(&mut ppu, LCDC | 0x60);
+        assert_eq!(ppu.framebuffer().get_pixel(158, 0), 0);
+        assert_eq!(ppu.framebuffer().get_pixel(159, 0), 3);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 1), 3);
+
+        /* Not drawn above WY, nor when turned off. */
+        ppu.write_reg(0xFF4A, 8);
+        ppu.write_reg(0xFF4B, 7);
+        run_frame(&mut ppu, LCDC | 0x60);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 7), 0);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 8), 3);
+        run_frame(&mut ppu, LCDC | 0x40);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 8), 0);
+    }
+
+    #[test]
+    fn window_line_counter() {
+        let mut ppu = ppu();
+        /* Tile 2 is only colored on its 5th line. */
+        ppu.vram[2 * TILE_SZ + 8] = 0xFF;
+        ppu.vram[2 * TILE_SZ + 9] = 0xFF;
+        fill_window(&mut ppu, 2);
+
+        ppu.write_reg(0xFF4A, 0);
+        ppu.write_reg(0xFF4B, 7);
+        ppu.write_reg(0xFF40, LCDC | 0x60);
+
+        /* Turned off on lines 4 to 7, line 8 draws the 5th line of the window. */
+        run_until_line(&mut ppu, 4);
+        ppu.write_reg(0xFF40, LCDC | 0x40);
+        run_until_line(&mut ppu, 8);
+        ppu.write_reg(0xFF40, LCDC | 0x60);
+        run_frame(&mut ppu, LCDC | 0x60);
+
+        assert_eq!(ppu.framebuffer().get_pixel(0, 4), 0);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 8), 3);
+    }
+
+    #[test]
+    fn transparency_and_bg_priority() {
+        let mut ppu = ppu();
+
+        /* At (0, 0), and one behind the background at (16, 0). */
+        set_sprite(&mut ppu, 0, 8, 16, 1, 0);
+        set_sprite(&mut ppu, 1, 24, 16, 1, SPRITE_BEHIND_BG);
+        run_frame(&mut ppu, LCDC);
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb.get_pixel(0, 0), 3);
+        assert_eq!(fb.get_pixel(3, 7), 3);
+        assert_eq!(fb.get_pixel(4, 0), 0);
+        assert_eq!(fb.get_pixel(0, 8), 0);
+        assert_eq!(fb.get_pixel(16, 0), 0);
+
+        /* Sprites behind the background show over its color 0. */
+        ppu.vram[..TILE_SZ].copy_from_slice(&[0; TILE_SZ]);
+        run_frame(&mut ppu, LCDC);
+        assert_eq!(ppu.framebuffer().get_pixel(16, 0), 3);
+
+        /* Or not at all when sprites are off. */
+        run_frame(&mut ppu, LCDC & !0x02);
+        assert_eq!(ppu.framebuffer().get_pixel(0, 0), 0);
+    }
+
+    #[test]
+    fn bg_off_is_white() {
+        let mut ppu = ppu();
+        /* Color 0 drawn black. */
+        ppu.write_reg(0xFF47, 0xE3);
+
+        set_sprite(&mut ppu, 0, 24, 16, 1, SPRITE_BEHIND_BG);
+        run_frame(&mut ppu, LCDC & !0x01);
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb.get_pixel(0, 0), 0);
+        assert_eq!(fb.get_pixel(16, 0), 3);
+    }
+
+    #[test]
+    fn flips_and_tall_sprites() {
+        let mut ppu = ppu();
+
+        /* In 8x16 mode tile 1 becomes tiles 0 then 1. */
+        set_sprite(&mut ppu, 0, 8, 16, 1, SPRITE_X_FLIP | SPRITE_OBP1);
+        set_sprite(&mut ppu, 1, 40, 16, 1, SPRITE_Y_FLIP);
+        run_frame(&mut ppu, LCDC | 0x04);
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb.get_pixel(0, 0), 2);
+        assert_eq!(fb.get_pixel(0, 8), 0);
+        assert_eq!(fb.get_pixel(7, 8), 2);
+        assert_eq!(fb.get_pixel(7, 16), 0);
+
+        assert_eq!(fb.get_pixel(32, 0), 3);
+        assert_eq!(fb.get_pixel(36, 0), 0);
+        assert_eq!(fb.get_pixel(36, 15), 1);
+    }
+
+    #[test]
+    fn ten_sprites_per_line() {
+        let mut ppu = ppu();
+
+        /* The 11th sprite of the line doesn't get drawn. */
+        for i in 0..11 {
+            set_sprite(&mut ppu, i, 8 + 8 * i as u8, 16, 1, 0);
+        }
+        run_frame(&mut ppu, LCDC);
+
+        assert_eq!(ppu.framebuffer().get_pixel(72, 0), 3);
+        assert_eq!(ppu.framebuffer().get_pixel(80, 0), 0);
+    }
+
+    #[test]
+    fn x_priority() {
+        let mut ppu = ppu();
+
+        /* The smaller X is on top even when later in OAM. */
+        set_sprite(&mut ppu, 0, 18, 16, 1, SPRITE_OBP1);
+        set_sprite(&mut ppu, 1, 16, 16, 1, 0);
+        run_frame(&mut ppu, LCDC);
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb.get_pixel(10, 0), 3);
+        /* Its transparent pixels show the other one. */
+        assert_eq!(fb.get_pixel(12, 0), 2);
+
+        /* Same X, the first in OAM is on top. */
+        set_sprite(&mut ppu, 1, 18, 16, 1, 0);
+        run_frame(&mut ppu, LCDC);
+        assert_eq!(ppu.framebuffer().get_pixel(10, 0), 2);
+    }
+}
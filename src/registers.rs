@@ -12,10 +12,10 @@ pub struct Registers {
 }
 
 pub enum CpuFlag {
-    Z = 0b0100_0000,
-    N = 0b0010_0000,
-    H = 0b0001_0000,
-    C = 0b0000_1000,
+    Z = 0b1000_0000,
+    N = 0b0100_0000,
+    H = 0b0010_0000,
+    C = 0b0001_0000,
 }
 
 impl Registers {
@@ -91,6 +91,12 @@ impl Registers {
     }
 }
 
+impl Default for Registers {
+    fn default() -> Registers {
+        Registers::new()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Registers;
@@ -133,19 +139,19 @@ mod test {
         regs.set_flag(CpuFlag::H, true);
         regs.set_flag(CpuFlag::C, true);
 
-        assert_eq!(regs.get_flag(CpuFlag::Z), true);
-        assert_eq!(regs.get_flag(CpuFlag::N), true);
-        assert_eq!(regs.get_flag(CpuFlag::H), true);
-        assert_eq!(regs.get_flag(CpuFlag::C), true);
+        assert!(regs.get_flag(CpuFlag::Z));
+        assert!(regs.get_flag(CpuFlag::N));
+        assert!(regs.get_flag(CpuFlag::H));
+        assert!(regs.get_flag(CpuFlag::C));
 
         regs.set_flag(CpuFlag::Z, false);
         regs.set_flag(CpuFlag::N, false);
         regs.set_flag(CpuFlag::H, false);
         regs.set_flag(CpuFlag::C, false);
 
-        assert_eq!(regs.get_flag(CpuFlag::Z), false);
-        assert_eq!(regs.get_flag(CpuFlag::N), false);
-        assert_eq!(regs.get_flag(CpuFlag::H), false);
-        assert_eq!(regs.get_flag(CpuFlag::C), false);
+        assert!(!regs.get_flag(CpuFlag::Z));
+        assert!(!regs.get_flag(CpuFlag::N));
+        assert!(!regs.get_flag(CpuFlag::H));
+        assert!(!regs.get_flag(CpuFlag::C));
     }
 }
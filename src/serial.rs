@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+
+/* A transfer shifts 8 bits out at 8192Hz. */
+const TRANSFER_CYCLES: usize = 8 * 512;
+
+/* Bytes kept until someone takes them, the oldest ones get dropped past that. */
+const MAX_OUTPUT_SIZE: usize = 0x10000;
+
+/* Nothing is ever plugged into the link port, so every byte received is 0xFF. What gets sent is
+ * kept around, test ROMs use it to report their results.
+ */
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    cycles_remaining: usize,
+    output: VecDeque<u8>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            sb: 0,
+            sc: 0,
+            cycles_remaining: 0,
+            output: VecDeque::new(),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.sb,
+            0xFF02 => self.sc | 0x7E, /* Bits 1-6 are unused and read as 1. */
+            _ => panic!("Invalid serial registers address {:4X}", addr),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xFF01 => self.sb = val,
+            0xFF02 => {
+                self.sc = val & 0x81;
+
+                /* Only transfers using the internal clock ever complete. */
+                if self.is_transferring() && self.sc & 1 > 0 {
+                    if self.output.len() == MAX_OUTPUT_SIZE {
+                        self.output.pop_front();
+                    }
+                    self.output.push_back(self.sb);
+                    self.cycles_remaining = TRANSFER_CYCLES;
+                }
+            }
+            _ => panic!("Invalid serial registers address {:4X}", addr),
+        }
+    }
+
+    /* Returns true when a transfer completed and the serial interrupt has to be requested. */
+    pub fn do_cycle(&mut self) -> bool {
+        if self.cycles_remaining == 0 {
+            return false;
+        }
+
+        self.cycles_remaining -= 1;
+
+        if self.cycles_remaining == 0 {
+            self.sb = 0xFF;
+            self.sc &= !0x80;
+            true
+        } else {
+            false
+        }
+    }
+
+    /* Returns the bytes sent since the last call. */
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.drain(..).collect()
+    }
+
+    fn is_transferring(&self) -> bool {
+        self.sc & 0x80 > 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(serial: &mut Serial, val: u8) {
+        serial.write(0xFF01, val);
+        serial.write(0xFF02, 0x81);
+        while !serial.do_cycle() {}
+    }
+
+    #[test]
+    fn output_taken_and_capped() {
+        let mut serial = Serial::new();
+
+        send(&mut serial, b'o');
+        send(&mut serial, b'k');
+        assert_eq!(serial.take_output(), b"ok");
+        assert!(serial.take_output().is_empty());
+
+        for i in 0..MAX_OUTPUT_SIZE + 2 {
+            send(&mut serial, i as u8);
+        }
+        let output = serial.take_output();
+        assert_eq!(output.len(), MAX_OUTPUT_SIZE);
+        assert_eq!(output[0], 2);
+    }
+}
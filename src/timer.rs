@@ -1,68 +1,98 @@
 pub struct Timer {
-    div: u8,
+    /* DIV is the upper byte of this internal counter, incremented every cycle. */
+    counter: u16,
     tima: u8,
     tma: u8,
     tac: u8,
-    cycles: usize,
+    /* Set when TIMA overflowed, until the interrupt gets requested. */
+    overflowed: bool,
 }
 
 impl Timer {
     pub fn new() -> Timer {
         Timer {
-            div: 0,
+            counter: 0,
             tima: 0,
             tma: 0,
             tac: 0,
-            cycles: 0,
+            overflowed: false,
         }
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
-            0xFF04 => self.div,
+            0xFF04 => (self.counter >> 8) as u8,
             0xFF05 => self.tima,
             0xFF06 => self.tma,
-            0xFF07 => self.tac,
+            0xFF07 => self.tac | 0xF8, /* Upper 5 bits are unused and read as 1. */
             _ => panic!("Invalid timer registers address {:2X}", addr),
         }
     }
 
     pub fn write(&mut self, addr: u16, val: u8) {
         match addr {
-            0xFF04 => self.div = 0x00,
+            0xFF04 => {
+                /* Resetting the counter can produce a falling edge on the selected bit. */
+                let was_high = self.timer_bit();
+                self.counter = 0;
+
+                if was_high {
+                    self.increment_tima();
+                }
+            }
             0xFF05 => self.tima = val,
             0xFF06 => self.tma = val,
-            0xFF07 => self.tac = val,
+            0xFF07 => {
+                /* Changing the selected bit or disabling the timer can also produce one. */
+                let was_high = self.timer_bit();
+                self.tac = val & 0b111;
+
+                if was_high && !self.timer_bit() {
+                    self.increment_tima();
+                }
+            }
             _ => panic!("Invalid timer registers address {:2X}", addr),
         }
     }
 
-    pub fn do_cycles(&mut self) {
-        if self.cycles % 0xFF  == 0 {
-            let (div, _) = self.div.overflowing_add(1);
-            self.div = div;
+    /* Returns true when TIMA overflowed and the timer interrupt has to be requested. */
+    pub fn do_cycles(&mut self) -> bool {
+        let was_high = self.timer_bit();
+        self.counter = self.counter.wrapping_add(1);
+
+        /* TIMA is incremented on the falling edge of the counter bit selected by TAC. */
+        if was_high && !self.timer_bit() {
+            self.increment_tima();
         }
 
-        if self.cycles % self.tac_cycles()  == 0 {
-            if self.tima == 0xFF {
+        let overflowed = self.overflowed;
+        self.overflowed = false;
+
+        overflowed
+    }
+
+    fn increment_tima(&mut self) {
+        match self.tima.checked_add(1) {
+            Some(tima) => self.tima = tima,
+            None => {
                 self.tima = self.tma;
-            } else {
-                let (tima, _) = self.tima.overflowing_add(1);
-                self.tima = tima;
+                self.overflowed = true;
             }
-
         }
+    }
 
-        self.cycles += 1;
+    fn is_enabled(&self) -> bool {
+        self.tac & 0b100 > 0
     }
 
-    fn tac_cycles(&self) -> usize {
-        match self.tac {
-            0b00 => 1024,
-            0b01 => 16,
-            0b10 => 64,
-            0b11 => 256,
-            _ => panic!("Invalid TAC value {:X}", self.tac),
-        }
+    fn timer_bit(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9, /* 4096 Hz, every 1024 cycles */
+            0b01 => 3, /* 262144 Hz, every 16 cycles */
+            0b10 => 5, /* 65536 Hz, every 64 cycles */
+            _ => 7,    /* 16384 Hz, every 256 cycles */
+        };
+
+        self.is_enabled() && self.counter & (1 << bit) > 0
     }
 }
@@ -0,0 +1,56 @@
+use std::path;
+
+use gameboy_rs::GameBoy;
+
+/* The combined cpu_instrs ROM takes about a minute of emulated time. */
+const CPU_INSTRS_MAX_FRAMES: usize = 60 * 120;
+
+const CPU_INSTRS_TESTS: [&str; 11] = [
+    "01-special",
+    "02-interrupts",
+    "03-op sp,hl",
+    "04-op r,imm",
+    "05-op rp",
+    "06-ld r,r",
+    "07-jr,jp,call,ret,rst",
+    "08-misc instrs",
+    "09-op r,r",
+    "10-bit ops",
+    "11-op a,(hl)",
+];
+
+#[test]
+fn blargg_cpu_instrs() {
+    let rom_path = path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/roms/cpu_instrs.gb"));
+    let mut gameboy = GameBoy::from_file(rom_path).unwrap();
+
+    /* The ROM reports each sub-test as "NN:ok" (or "NN:<failed check>") through the serial port,
+     * then prints either "Passed" or "Failed".
+     */
+    let mut serial_output = Vec::new();
+
+    for _ in 0..CPU_INSTRS_MAX_FRAMES {
+        gameboy.run_frame();
+        serial_output.extend(gameboy.take_serial_output());
+
+        let output = String::from_utf8_lossy(&serial_output);
+        if output.contains("Passed") || output.contains("Failed") {
+            break;
+        }
+    }
+
+    let output = String::from_utf8_lossy(&serial_output).into_owned();
+    let failed: Vec<&str> = CPU_INSTRS_TESTS
+        .iter()
+        .enumerate()
+        .filter(|(n, _)| !output.contains(&format!("{:02}:ok", n + 1)))
+        .map(|(_, name)| *name)
+        .collect();
+
+    assert!(
+        output.contains("Passed") && failed.is_empty(),
+        "cpu_instrs failed {:?}, serial output:\n{}",
+        failed,
+        output
+    );
+}